@@ -0,0 +1,261 @@
+use std::f32::consts::PI;
+
+use crate::{
+    bezier::Vec2,
+    grid::{transform_glyph_verts, GlVertex},
+};
+
+// Transform of a single glyph, applied around the center of its quad
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphTransform {
+    pub offset: [f32; 2],
+    pub rotation: f32, // Radians, counter-clockwise
+    pub scale: f32,
+    pub alpha: f32, // Multiplies the glyph's own alpha
+}
+
+impl Default for GlyphTransform {
+    fn default() -> Self {
+        GlyphTransform {
+            offset: [0.0, 0.0],
+            rotation: 0.0,
+            scale: 1.0,
+            alpha: 1.0,
+        }
+    }
+}
+
+pub enum Effect {
+    // Reveals glyphs one after another
+    Typewriter {
+        glyphs_per_second: f32,
+    },
+    // Moves glyphs up and down along a sine wave travelling through the
+    // text. Wavelength is in glyphs, speed in waves per second.
+    Wave {
+        amplitude: f32,
+        wavelength: f32,
+        speed: f32,
+    },
+    // Fades each glyph in over `duration` seconds, starting `stagger`
+    // seconds after the previous glyph
+    FadeIn {
+        duration: f32,
+        stagger: f32,
+    },
+    // Jitters glyphs by up to `amplitude`, picking a new offset
+    // `frequency` times per second
+    Shake {
+        amplitude: f32,
+        frequency: f32,
+    },
+    // Called with the glyph index and the time in seconds
+    Custom(Box<dyn Fn(usize, f32) -> GlyphTransform>),
+}
+
+impl Effect {
+    pub fn transform(&self, index: usize, time: f32) -> GlyphTransform {
+        let i = index as f32;
+        match self {
+            Effect::Typewriter { glyphs_per_second } => GlyphTransform {
+                alpha: if i < time * glyphs_per_second {
+                    1.0
+                } else {
+                    0.0
+                },
+                ..GlyphTransform::default()
+            },
+            Effect::Wave {
+                amplitude,
+                wavelength,
+                speed,
+            } => GlyphTransform {
+                offset: [
+                    0.0,
+                    amplitude * (2.0 * PI * (i / wavelength - time * speed)).sin(),
+                ],
+                ..GlyphTransform::default()
+            },
+            Effect::FadeIn { duration, stagger } => GlyphTransform {
+                alpha: ((time - i * stagger) / duration).clamp(0.0, 1.0),
+                ..GlyphTransform::default()
+            },
+            Effect::Shake {
+                amplitude,
+                frequency,
+            } => {
+                let step = (time * frequency).floor() as u32;
+                let seed = (index as u32).wrapping_mul(0x9e37_79b9) ^ step;
+                GlyphTransform {
+                    offset: [
+                        amplitude * noise(seed.wrapping_mul(2)),
+                        amplitude * noise(seed.wrapping_mul(2).wrapping_add(1)),
+                    ],
+                    ..GlyphTransform::default()
+                }
+            }
+            Effect::Custom(f) => f(index, time),
+        }
+    }
+}
+
+// Deterministic value in [-1, 1] from an integer hash
+fn noise(seed: u32) -> f32 {
+    let mut x = seed;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+// Rewrites the six vertices of every glyph in `verts` from the
+// untransformed `base` vertices, applying the effect at `time`.
+// Both slices must hold the same glyphs, six vertices each.
+pub fn animate(base: &[GlVertex], verts: &mut [GlVertex], effect: &Effect, time: f32) {
+    assert_eq!(base.len(), verts.len());
+    assert_eq!(base.len() % 6, 0);
+
+    for (index, (base, quad)) in base.chunks(6).zip(verts.chunks_mut(6)).enumerate() {
+        let t = effect.transform(index, time);
+        quad.copy_from_slice(base);
+        transform_glyph_verts(
+            quad,
+            quad_center(base),
+            t.rotation,
+            t.scale,
+            Vec2::from(t.offset[0], t.offset[1]),
+        );
+        for v in quad.iter_mut() {
+            v.color[3] = (v.color[3] as f32 * t.alpha.clamp(0.0, 1.0)) as u8;
+        }
+    }
+}
+
+fn quad_center(quad: &[GlVertex]) -> Vec2 {
+    let mut min = Vec2::from(f32::MAX, f32::MAX);
+    let mut max = Vec2::from(f32::MIN, f32::MIN);
+    for v in quad {
+        min = Vec2::from(min.x.min(v.pos.x), min.y.min(v.pos.y));
+        max = Vec2::from(max.x.max(v.pos.x), max.y.max(v.pos.y));
+    }
+    Vec2::from((min.x + max.x) / 2.0, (min.y + max.y) / 2.0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::{bezier::Vec2, grid::GlVertex};
+
+    use super::{animate, Effect, GlyphTransform};
+
+    // A row of 2x2 glyphs with advance 4
+    fn glyphs(n: usize) -> Vec<GlVertex> {
+        let mut verts = Vec::new();
+        for i in 0..n {
+            let x = i as f32 * 4.0;
+            for (dx, dy) in [(0, 0), (2, 0), (0, 2), (2, 2), (0, 2), (2, 0)] {
+                verts.push(GlVertex {
+                    pos: Vec2::from(x + dx as f32, dy as f32),
+                    data: 0,
+                    color: [255, 0, 0, 255],
+                });
+            }
+        }
+        verts
+    }
+
+    fn positions(verts: &[GlVertex]) -> Vec<(f32, f32)> {
+        verts
+            .iter()
+            .map(|v| ((v.pos.x * 1e3).round() / 1e3, (v.pos.y * 1e3).round() / 1e3))
+            .collect()
+    }
+
+    fn alphas(verts: &[GlVertex]) -> Vec<u8> {
+        verts.iter().step_by(6).map(|v| v.color[3]).collect()
+    }
+
+    #[test]
+    fn test_typewriter() {
+        let base = glyphs(4);
+        let mut verts = base.clone();
+        let effect = Effect::Typewriter {
+            glyphs_per_second: 10.0,
+        };
+        animate(&base, &mut verts, &effect, 0.25);
+        assert_eq!(alphas(&verts), [255, 255, 255, 0]);
+        assert_eq!(positions(&verts), positions(&base));
+    }
+
+    #[test]
+    fn test_wave() {
+        let base = glyphs(4);
+        let mut verts = base.clone();
+        let effect = Effect::Wave {
+            amplitude: 3.0,
+            wavelength: 4.0,
+            speed: 1.0,
+        };
+        animate(&base, &mut verts, &effect, 0.0);
+        let ys: Vec<f32> = positions(&verts).iter().step_by(6).map(|p| p.1).collect();
+        assert_eq!(ys, [0.0, 3.0, 0.0, -3.0]);
+    }
+
+    #[test]
+    fn test_fade_in() {
+        let base = glyphs(3);
+        let mut verts = base.clone();
+        let effect = Effect::FadeIn {
+            duration: 1.0,
+            stagger: 0.5,
+        };
+        animate(&base, &mut verts, &effect, 1.0);
+        assert_eq!(alphas(&verts), [255, 127, 0]);
+    }
+
+    #[test]
+    fn test_shake() {
+        let base = glyphs(3);
+        let effect = Effect::Shake {
+            amplitude: 1.0,
+            frequency: 10.0,
+        };
+        let mut a = base.clone();
+        let mut b = base.clone();
+        animate(&base, &mut a, &effect, 0.31);
+        animate(&base, &mut b, &effect, 0.39);
+        assert_eq!(positions(&a), positions(&b));
+        for (p, q) in positions(&a).iter().zip(positions(&base)) {
+            assert!((p.0 - q.0).abs() <= 1.0 && (p.1 - q.1).abs() <= 1.0);
+        }
+        assert_ne!(positions(&a), positions(&base));
+    }
+
+    #[test]
+    fn test_custom() {
+        let base = glyphs(2);
+        let mut verts = base.clone();
+        let effect = Effect::Custom(Box::new(|index, _| GlyphTransform {
+            rotation: FRAC_PI_2 * index as f32,
+            scale: 2.0,
+            ..GlyphTransform::default()
+        }));
+        animate(&base, &mut verts, &effect, 0.0);
+        // Scaled around each glyph's center, the second also rotated
+        assert_eq!(
+            positions(&verts[0..6]),
+            [
+                (-1.0, -1.0),
+                (3.0, -1.0),
+                (-1.0, 3.0),
+                (3.0, 3.0),
+                (-1.0, 3.0),
+                (3.0, -1.0)
+            ]
+        );
+        assert_eq!(positions(&verts[6..8]), [(7.0, -1.0), (7.0, 3.0)]);
+    }
+}
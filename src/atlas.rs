@@ -0,0 +1,526 @@
+use crate::grid::{
+    kAtlasChannels, kBezierAtlasSize, kBezierIndexFirstReal, kGridAtlasLayers, kGridAtlasSize,
+    AtlasError,
+};
+
+// Size of the bezier indices in the grid atlas of a page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IndexWidth {
+    Narrow, // RGBA8 texels
+    Wide,   // RGBA16UI texels, for glyphs with too many beziers for Narrow
+}
+
+impl IndexWidth {
+    // Bytes per index
+    pub fn bytes(self) -> usize {
+        match self {
+            IndexWidth::Narrow => 1,
+            IndexWidth::Wide => 2,
+        }
+    }
+
+    // Most beziers a glyph can have
+    pub fn max_beziers(self) -> usize {
+        let max_index = match self {
+            IndexWidth::Narrow => u8::MAX as usize,
+            IndexWidth::Wide => u16::MAX as usize,
+        };
+        max_index - kBezierIndexFirstReal as usize + 1
+    }
+}
+
+// Packs rectangles into horizontal shelves of a square texture. Each
+// shelf is as tall as the first rectangle placed on it, and rectangles
+// go on the shortest shelf they fit on, so glyphs with similar grid
+// heights end up sharing shelves. Freed rectangles, and the space left
+// below rectangles shorter than their shelf, are kept in a free list and
+// reused before any new shelf space.
+#[derive(Clone)]
+pub struct ShelfPacker {
+    size: u16,
+    shelves: Vec<Shelf>,
+    free: Vec<[u16; 4]>, // XYWH
+    used: u32,           // Area of the rectangles currently inserted
+}
+
+#[derive(Clone)]
+struct Shelf {
+    y: u16,
+    height: u16,
+    next_x: u16,
+}
+
+impl ShelfPacker {
+    pub fn new(size: u16) -> ShelfPacker {
+        ShelfPacker {
+            size,
+            shelves: Vec::new(),
+            free: Vec::new(),
+            used: 0,
+        }
+    }
+
+    // Returns the XY position of a free w x h rectangle, or None if the
+    // texture has no room for it
+    pub fn insert(&mut self, w: u16, h: u16) -> Option<[u16; 2]> {
+        if w > self.size || h > self.size {
+            return None;
+        }
+
+        // The smallest freed rectangle that fits, with what's left of it
+        // split into the strip right of the new one and the strip below
+        let freed = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r[2] >= w && r[3] >= h)
+            .min_by_key(|(_, r)| r[2] as u32 * r[3] as u32)
+            .map(|(i, _)| i);
+        if let Some(i) = freed {
+            let [x, y, free_w, free_h] = self.free.swap_remove(i);
+            if free_w > w {
+                self.free.push([x + w, y, free_w - w, h]);
+            }
+            if free_h > h {
+                self.free.push([x, y + h, free_w, free_h - h]);
+            }
+            self.used += w as u32 * h as u32;
+            return Some([x, y]);
+        }
+
+        let size = self.size;
+        let best = self
+            .shelves
+            .iter_mut()
+            .filter(|s| s.height >= h && size - s.next_x >= w)
+            .min_by_key(|s| s.height);
+        if let Some(shelf) = best {
+            let pos = [shelf.next_x, shelf.y];
+            let rest = [pos[0], pos[1] + h, w, shelf.height - h];
+            shelf.next_x += w;
+            self.used += w as u32 * h as u32;
+            if rest[3] > 0 {
+                self.insert_free(rest);
+            }
+            return Some(pos);
+        }
+
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        if size - y < h {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height: h,
+            next_x: w,
+        });
+        self.used += w as u32 * h as u32;
+        Some([0, y])
+    }
+
+    // Makes a rectangle returned by insert available again. Once nothing
+    // is left, the whole texture is free for shelves again.
+    pub fn free(&mut self, pos: [u16; 2], w: u16, h: u16) {
+        self.used -= w as u32 * h as u32;
+        if self.used == 0 {
+            self.shelves.clear();
+            self.free.clear();
+        } else {
+            self.insert_free([pos[0], pos[1], w, h]);
+        }
+    }
+
+    // Adds a rectangle to the free list, merged with the free rectangles
+    // it shares a whole edge with. Space freed at the end of a shelf goes
+    // back to the shelf, and empty shelves at the bottom are dropped.
+    fn insert_free(&mut self, mut rect: [u16; 4]) {
+        let touches = |a: &[u16; 4], b: &[u16; 4]| {
+            (a[1] == b[1] && a[3] == b[3] && (a[0] + a[2] == b[0] || b[0] + b[2] == a[0]))
+                || (a[0] == b[0] && a[2] == b[2] && (a[1] + a[3] == b[1] || b[1] + b[3] == a[1]))
+        };
+        while let Some(i) = self.free.iter().position(|r| touches(r, &rect)) {
+            let r = self.free.swap_remove(i);
+            rect = if r[1] == rect[1] && r[3] == rect[3] {
+                [r[0].min(rect[0]), r[1], r[2] + rect[2], r[3]]
+            } else {
+                [r[0], r[1].min(rect[1]), r[2], r[3] + rect[3]]
+            };
+        }
+        self.free.push(rect);
+
+        loop {
+            let shelves = &self.shelves;
+            let tail = self.free.iter().enumerate().find_map(|(i, r)| {
+                shelves
+                    .iter()
+                    .position(|s| r[1] == s.y && r[3] == s.height && r[0] + r[2] == s.next_x)
+                    .map(|shelf| (i, shelf))
+            });
+            match tail {
+                Some((i, shelf)) => self.shelves[shelf].next_x = self.free.swap_remove(i)[0],
+                None => break,
+            }
+        }
+        while self.shelves.last().is_some_and(|s| s.next_x == 0) {
+            self.shelves.pop();
+        }
+    }
+
+    // Texels covered by inserted rectangles
+    pub fn used(&self) -> usize {
+        self.used as usize
+    }
+}
+
+// Hands out space in the glyph data buffer, in texels. All data for a
+// single glyph must lie in a single row, although multiple glyphs can be
+// in one row, so a glyph that doesn't fit in the rest of the current row
+// starts on the next one. Freed ranges are reused before the space
+// after the last allocation.
+#[derive(Clone)]
+pub struct GlyphDataAllocator {
+    row_size: u32,
+    rows: u32,
+    next: u32,             // Texel offset of the next never used texel
+    free: Vec<(u32, u32)>, // Offset and length, sorted by offset
+    used: u32,
+}
+
+impl GlyphDataAllocator {
+    pub fn new(row_size: u32, rows: u32) -> GlyphDataAllocator {
+        GlyphDataAllocator {
+            row_size,
+            rows,
+            next: 0,
+            free: Vec::new(),
+            used: 0,
+        }
+    }
+
+    // Returns the texel offset of `len` free texels in one row
+    pub fn alloc(&mut self, len: usize) -> Result<u32, AtlasError> {
+        if len > self.row_size as usize {
+            return Err(AtlasError::GlyphDataTooLarge { texels: len });
+        }
+        let len = len as u32;
+
+        // The shortest freed range that fits
+        let freed = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.1 >= len)
+            .min_by_key(|(_, r)| r.1)
+            .map(|(i, _)| i);
+        if let Some(i) = freed {
+            let (offset, free_len) = self.free[i];
+            if free_len > len {
+                self.free[i] = (offset + len, free_len - len);
+            } else {
+                self.free.remove(i);
+            }
+            self.used += len;
+            return Ok(offset);
+        }
+
+        let mut offset = self.next;
+        if offset % self.row_size + len > self.row_size {
+            offset += self.row_size - offset % self.row_size;
+        }
+        if offset + len > self.row_size * self.rows {
+            return Err(AtlasError::GlyphDataFull);
+        }
+        // The tail of the row skipped over is still free for shorter data
+        if offset > self.next {
+            self.insert_free(self.next, offset - self.next);
+        }
+
+        self.next = offset + len;
+        self.used += len;
+        Ok(offset)
+    }
+
+    // Makes a range returned by alloc available again, merging it with
+    // neighbouring free ranges in the same row. Once nothing is left, the
+    // whole buffer is free again.
+    pub fn free(&mut self, offset: u32, len: usize) {
+        let len = len as u32;
+        self.used -= len;
+        if self.used == 0 {
+            self.next = 0;
+            self.free.clear();
+        } else {
+            self.insert_free(offset, len);
+        }
+    }
+
+    fn insert_free(&mut self, offset: u32, len: u32) {
+        let i = self.free.partition_point(|r| r.0 < offset);
+        self.free.insert(i, (offset, len));
+        let row_size = self.row_size;
+        let row = |offset: u32| offset / row_size;
+        if i + 1 < self.free.len() {
+            let (next_offset, next_len) = self.free[i + 1];
+            if offset + len == next_offset && row(offset) == row(next_offset) {
+                self.free[i].1 += next_len;
+                self.free.remove(i + 1);
+            }
+        }
+        if i > 0 {
+            let (prev_offset, prev_len) = self.free[i - 1];
+            if prev_offset + prev_len == offset && row(prev_offset) == row(offset) {
+                self.free[i - 1].1 += self.free[i].1;
+                self.free.remove(i);
+            }
+        }
+        // Space freed right before the next never used texel is simply
+        // never used again
+        while let Some(&(last_offset, last_len)) = self.free.last() {
+            if last_offset + last_len != self.next {
+                break;
+            }
+            self.next = last_offset;
+            self.free.pop();
+        }
+    }
+
+    // Texels currently allocated
+    pub fn used(&self) -> usize {
+        self.used as usize
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.row_size * self.rows) as usize
+    }
+}
+
+// The atlas space of a glyph: its grid position and size, and the offset
+// and length of its data
+pub(crate) type GlyphSpace = ([u16; 2], [u16; 2], (u32, usize));
+
+// Grid atlas contains an array of grids, one per glyph, packed by the
+// grid_packer. Each grid takes a single glyph and splits it into cells
+// that inform the fragment shader which curves of the glyph intersect
+// that cell. The cell contains coords to data in the bezier atlas. The
+// bezier atlas (glyph_data_buf) contains the actual bezier curves for
+// each glyph. Each bezier curve takes three "RGBA pixels" (12 bytes) of
+// data. Both atlases also encode some extra information, which is
+// explained where it is used in the code.
+// A glyph only ever references the atlases of the group (page) it was
+// written to, so each page is drawn separately.
+pub struct AtlasGroup {
+    pub index_width: IndexWidth,
+    pub grid_atlas: Vec<u8>,
+    pub(crate) grid_packer: ShelfPacker,
+    pub uploaded: bool, // Cleared whenever the atlases change
+
+    pub glyph_data_buf: Vec<u16>,
+    pub(crate) glyph_data_alloc: GlyphDataAllocator,
+}
+
+impl Default for AtlasGroup {
+    fn default() -> Self {
+        AtlasGroup::new(IndexWidth::Narrow)
+    }
+}
+
+impl AtlasGroup {
+    pub fn new(index_width: IndexWidth) -> Self {
+        let data_texels = kBezierAtlasSize as u32 * kBezierAtlasSize as u32;
+        AtlasGroup {
+            index_width,
+            grid_atlas: vec![
+                0;
+                kGridAtlasSize as usize
+                    * kGridAtlasSize as usize
+                    * kAtlasChannels as usize
+                    * index_width.bytes()
+                    * kGridAtlasLayers as usize
+            ],
+            grid_packer: ShelfPacker::new(kGridAtlasSize),
+            uploaded: true,
+            glyph_data_buf: vec![
+                0;
+                kBezierAtlasSize as usize
+                    * kBezierAtlasSize as usize
+                    * (kAtlasChannels / 2) as usize
+            ],
+            // The shader reads glyph data through a buffer texture, so
+            // the whole buffer is one row whatever the index width
+            glyph_data_alloc: GlyphDataAllocator::new(data_texels, 1),
+        }
+    }
+
+    // Reserves a grid_width x grid_height rectangle of the grid atlas and
+    // data_len texels of the glyph data buffer, or neither. Returns the
+    // grid position and the data offset.
+    pub(crate) fn reserve(
+        &mut self,
+        grid_width: u16,
+        grid_height: u16,
+        data_len: usize,
+    ) -> Option<([u16; 2], u32)> {
+        let data_offset = self.glyph_data_alloc.alloc(data_len).ok()?;
+        match self.grid_packer.insert(grid_width, grid_height) {
+            Some(grid_pos) => Some((grid_pos, data_offset)),
+            None => {
+                self.glyph_data_alloc.free(data_offset, data_len);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn release(&mut self, grid_pos: [u16; 2], grid_size: [u16; 2], data: (u32, usize)) {
+        self.grid_packer.free(grid_pos, grid_size[0], grid_size[1]);
+        self.glyph_data_alloc.free(data.0, data.1);
+    }
+
+    // How many of `releases`, released in order, it takes before reserve
+    // would succeed, or None if releasing all of them isn't enough. Tried
+    // out on a copy of the packers, so the page doesn't change.
+    pub(crate) fn releases_to_fit(
+        &self,
+        releases: &[GlyphSpace],
+        grid_width: u16,
+        grid_height: u16,
+        data_len: usize,
+    ) -> Option<usize> {
+        let mut space = AtlasGroup {
+            index_width: self.index_width,
+            grid_atlas: Vec::new(),
+            grid_packer: self.grid_packer.clone(),
+            uploaded: self.uploaded,
+            glyph_data_buf: Vec::new(),
+            glyph_data_alloc: self.glyph_data_alloc.clone(),
+        };
+        for n in 0..=releases.len() {
+            if space.reserve(grid_width, grid_height, data_len).is_some() {
+                return Some(n);
+            }
+            if let Some(&(grid_pos, grid_size, data)) = releases.get(n) {
+                space.release(grid_pos, grid_size, data);
+            }
+        }
+        None
+    }
+}
+
+// Occupancy of a grid's atlases, summed over all pages
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct AtlasStats {
+    pub pages: usize,
+    pub grid_cells_used: usize,
+    pub grid_cells_total: usize,
+    pub glyph_data_used: usize, // Texels
+    pub glyph_data_total: usize,
+    pub cached_glyphs: usize,
+    pub referenced_glyphs: usize, // Cached glyphs drawn by at least one quad
+    pub evictions: usize,         // Since the grid was created
+}
+
+#[cfg(test)]
+mod test {
+    use crate::grid::AtlasError;
+
+    use super::{GlyphDataAllocator, ShelfPacker};
+
+    #[test]
+    fn test_shelf_packer() {
+        let mut packer = ShelfPacker::new(16);
+        assert_eq!(packer.insert(5, 5), Some([0, 0]));
+        assert_eq!(packer.insert(4, 8), Some([0, 5]));
+        // Shortest shelf that fits
+        assert_eq!(packer.insert(5, 3), Some([5, 0]));
+        assert_eq!(packer.insert(5, 6), Some([4, 5]));
+        // The first shelf is out of room
+        assert_eq!(packer.insert(7, 4), Some([9, 5]));
+        // The space below a rectangle shorter than its shelf
+        assert_eq!(packer.insert(7, 4), Some([9, 9]));
+        // No shelf has room and a new 4 high one doesn't fit below them
+        assert_eq!(packer.insert(7, 4), None);
+        assert_eq!(packer.insert(7, 3), Some([0, 13]));
+        assert_eq!(packer.insert(17, 1), None);
+    }
+
+    #[test]
+    fn test_glyph_data_allocator() {
+        let mut alloc = GlyphDataAllocator::new(10, 3);
+        assert_eq!(alloc.alloc(4), Ok(0));
+        assert_eq!(alloc.alloc(4), Ok(4));
+        // Doesn't fit in the rest of the first row
+        assert_eq!(alloc.alloc(3), Ok(10));
+        assert_eq!(alloc.alloc(7), Ok(13));
+        assert_eq!(alloc.alloc(10), Ok(20));
+        // The tail of the first row was kept
+        assert_eq!(alloc.alloc(2), Ok(8));
+        assert_eq!(alloc.alloc(1), Err(AtlasError::GlyphDataFull));
+        assert_eq!(
+            alloc.alloc(11),
+            Err(AtlasError::GlyphDataTooLarge { texels: 11 })
+        );
+    }
+
+    #[test]
+    fn test_shelf_packer_free() {
+        let mut packer = ShelfPacker::new(16);
+        assert_eq!(packer.insert(8, 8), Some([0, 0]));
+        assert_eq!(packer.insert(8, 8), Some([8, 0]));
+        assert_eq!(packer.used(), 128);
+
+        packer.free([0, 0], 8, 8);
+        assert_eq!(packer.used(), 64);
+        // Reused, with the rest split into the right and bottom strips
+        assert_eq!(packer.insert(5, 6), Some([0, 0]));
+        assert_eq!(packer.insert(3, 6), Some([5, 0]));
+        assert_eq!(packer.insert(8, 2), Some([0, 6]));
+        // Too big for any freed rectangle, so it goes on a new shelf
+        assert_eq!(packer.insert(8, 8), Some([0, 8]));
+        assert_eq!(packer.used(), 192);
+
+        // Neighbouring freed rectangles merge back into one
+        packer.free([0, 0], 5, 6);
+        packer.free([5, 0], 3, 6);
+        packer.free([0, 6], 8, 2);
+        assert_eq!(packer.insert(8, 8), Some([0, 0]));
+        // Space freed at the end of a shelf goes back to it, so the
+        // emptied bottom shelf is gone and a wider one takes its place
+        packer.free([0, 8], 8, 8);
+        assert_eq!(packer.insert(16, 8), Some([0, 8]));
+        assert_eq!(packer.used(), 256);
+
+        // Once empty, the texture is packed from scratch
+        for (pos, w) in [([0, 0], 8), ([8, 0], 8), ([0, 8], 16)] {
+            packer.free(pos, w, 8);
+        }
+        assert_eq!(packer.insert(16, 16), Some([0, 0]));
+    }
+
+    #[test]
+    fn test_glyph_data_allocator_free() {
+        let mut alloc = GlyphDataAllocator::new(10, 3);
+        for offset in [0, 3, 6, 10] {
+            assert_eq!(alloc.alloc(3), Ok(offset));
+        }
+        assert_eq!(alloc.used(), 12);
+
+        alloc.free(3, 3);
+        alloc.free(10, 3);
+        assert_eq!(alloc.alloc(2), Ok(3));
+        // Merged with the skipped row tail, but not the next row
+        alloc.free(6, 3);
+        assert_eq!(alloc.alloc(5), Ok(5));
+        assert_eq!(alloc.alloc(6), Ok(10));
+        assert_eq!(alloc.alloc(1), Ok(16));
+        assert_eq!(alloc.used(), 17);
+
+        // Freeing the last allocation hands its space back
+        alloc.free(16, 1);
+        assert_eq!(alloc.alloc(4), Ok(16));
+        // Freeing everything makes the whole buffer free again
+        for (offset, len) in [(0, 3), (3, 2), (5, 5), (10, 6), (16, 4)] {
+            alloc.free(offset, len);
+        }
+        assert_eq!(alloc.used(), 0);
+        assert_eq!(alloc.alloc(10), Ok(0));
+    }
+}
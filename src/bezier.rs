@@ -42,6 +42,14 @@ impl Bezier2 {
             c: Vec2 { x: x5, y: x6 },
         }
     }
+    pub fn point_at(&self, t: f32) -> Vec2 {
+        let mt = 1.0 - t;
+        Vec2 {
+            x: mt * mt * self.e0.x + 2.0 * t * mt * self.c.x + t * t * self.e1.x,
+            y: mt * mt * self.e0.y + 2.0 * t * mt * self.c.y + t * t * self.e1.y,
+        }
+    }
+
     pub fn intersect_vert(&self, x: f32) -> Vec<f32> {
         let inverse = Bezier2 {
             e0: Vec2 {
@@ -92,6 +100,20 @@ impl Bezier2 {
         }
         ret
     }
+
+    // Bounds of the control points, which contain the whole curve.
+    // Returns the min and max corners.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let min = Vec2::from(
+            self.e0.x.min(self.c.x).min(self.e1.x),
+            self.e0.y.min(self.c.y).min(self.e1.y),
+        );
+        let max = Vec2::from(
+            self.e0.x.max(self.c.x).max(self.e1.x),
+            self.e0.y.max(self.c.y).max(self.e1.y),
+        );
+        (min, max)
+    }
 }
 
 #[cfg(test)]
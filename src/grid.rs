@@ -1,22 +1,78 @@
 use std::{
-    cmp::min,
-    collections::{HashMap, HashSet},
+    cmp::{min, Reverse},
+    collections::HashMap,
+    fmt,
+    iter::FromIterator,
+    ops::Range,
 };
 
-use ordered_float::OrderedFloat;
-
 use crate::{
+    atlas::{AtlasGroup, AtlasStats, IndexWidth},
     bezier::{Bezier2, Vec2},
     buffer::write_glyph_data_to_buffer,
 };
 
-const kBezierIndexUnused: u8 = 0;
-const kBezierIndexFirstReal: u8 = 2;
-const kBezierIndexSortMeta: u8 = 1;
+const kBezierIndexUnused: u16 = 0;
+pub(crate) const kBezierIndexFirstReal: u16 = 2;
+const kBezierIndexSortMeta: u16 = 1;
 pub static kGridAtlasSize: u16 = 256; // Fits exactly 1024 8x8 grids
 pub static kAtlasChannels: u8 = 4; // Must be 4 (RGBA), otherwise code breaks
 pub static kBezierAtlasSize: u16 = 256; // Fits around 700-1000 glyphs, depending on their curves
 pub static kGridMaxSize: u8 = 20;
+pub static kGridAtlasLayers: u8 = 2; // Texels per cell, each holding 4 bezier indices
+pub static kMaxAtlasPages: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum AtlasError {
+    // More beziers intersect a grid cell than its texels can index
+    TooManyBeziersInCell { cell: usize, beziers: usize },
+    // A glyph has more beziers than the index width can address
+    TooManyBeziers { beziers: usize, max: usize },
+    // A glyph's grid is larger than an empty grid atlas
+    GridTooLarge { width: u16, height: u16 },
+    // The glyph data buffer has no room for a glyph's data
+    GlyphDataFull,
+    // Every page is full of glyphs that are still drawn
+    AtlasFull { pages: usize },
+    // A glyph's data is longer than an empty glyph data buffer
+    GlyphDataTooLarge { texels: usize },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::TooManyBeziersInCell { cell, beziers } => write!(
+                f,
+                "Too many beziers in grid cell {} ({}, at most {})",
+                cell,
+                beziers,
+                kAtlasChannels as usize * kGridAtlasLayers as usize
+            ),
+            AtlasError::TooManyBeziers { beziers, max } => write!(
+                f,
+                "Glyph has {} beziers, more than the {} its grid can index",
+                beziers, max
+            ),
+            AtlasError::GridTooLarge { width, height } => write!(
+                f,
+                "Grid of {}x{} cells doesn't fit in the grid atlas",
+                width, height
+            ),
+            AtlasError::GlyphDataFull => write!(f, "Glyph data buffer is full"),
+            AtlasError::AtlasFull { pages } => {
+                write!(f, "All {} atlas pages are full of glyphs in use", pages)
+            }
+            AtlasError::GlyphDataTooLarge { texels } => write!(
+                f,
+                "Glyph data of {} texels doesn't fit in a buffer of {}",
+                texels,
+                kBezierAtlasSize as usize * kBezierAtlasSize as usize
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
 
 #[repr(C, packed)]
 #[derive(Default, Copy, Clone)]
@@ -34,64 +90,301 @@ pub struct GlVertex {
     pub color: [u8; 4],
 }
 
+// Scales and rotates (radians, counter-clockwise) the vertices of a
+// glyph around `pivot`, then moves them by `offset`. Only positions
+// change, so the quad still samples the same glyph data; the fragment
+// shader derives its antialiasing from the screen-space derivatives of
+// the norm coords and doesn't need axis-aligned quads.
+pub fn transform_glyph_verts(
+    verts: &mut [GlVertex],
+    pivot: Vec2,
+    angle: f32,
+    scale: f32,
+    offset: Vec2,
+) {
+    let (sin, cos) = angle.sin_cos();
+    for v in verts.iter_mut() {
+        let x = (v.pos.x - pivot.x) * scale;
+        let y = (v.pos.y - pivot.y) * scale;
+        v.pos = Vec2::from(
+            x * cos - y * sin + pivot.x + offset.x,
+            x * sin + y * cos + pivot.y + offset.y,
+        );
+    }
+}
+
 #[derive(Default)]
 struct Glyph {
     size: [u16; 2],           // Width and height in FT units
     offset: [i16; 2],         // Offset of glyph in FT units
-    bezierAtlasPos: [u16; 2], // XZ pixel coordinates (Z being atlas index)
+    bezierAtlasPos: [u16; 2], // Texel offset into glyphDataBuf, and atlas page
     advance: i16,             // Amount to advance after character in FT units
 }
 
+// Where compact() moved a glyph's data, as a texel offset into the glyph
+// data buffer of a page, the same as GlVertex::data bits 2-31
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Relocation {
+    pub old_page: u16,
+    pub old_offset: u16,
+    pub new_page: u16,
+    pub new_offset: u16,
+}
+
+// A glyph written to the atlases, shared by all quads drawing it
+struct CacheEntry {
+    glyph: Glyph,
+    key: Option<u32>, // None if inserted without a cache key
+    grid_pos: [u16; 2],
+    grid_size: [u16; 2],
+    data_len: usize, // Texels
+    refs: usize,     // Quads drawing the glyph
+    last_used: u64,
+}
+
 struct Buffers {}
 
-fn find_cells_intersections(
-    beziers: &Vec<Bezier2>,
-    glyph_size: &Vec2,
-    grid_width: u32,
-    grid_height: u32,
-) -> Vec<HashSet<usize>> {
-    let mut ret: Vec<HashSet<usize>> = Vec::new();
-    ret.resize((grid_width * grid_height) as usize, HashSet::new());
-
-    let mut setgrid = |x: f32, y: f32, bezier_index: usize| {
-        let x = x.clamp(0.0, grid_width as f32 - 1.0) as u32;
-        let y = y.clamp(0.0, grid_height as f32 - 1.0) as u32;
-        let i = (y * grid_width) + x;
-        (&mut ret[i as usize]).insert(bezier_index);
-    };
+// Sorted, deduplicated indices of the beziers intersecting a grid cell.
+// Up to kCellInlineBeziers are stored inline, which covers every cell
+// that fits in the atlas, so only overfull cells allocate.
+#[derive(Clone, Default, Debug, PartialEq)]
+struct CellBeziers {
+    len: usize,
+    inline: [u32; kCellInlineBeziers],
+    spilled: Vec<u32>, // Holds all of them once there are too many
+}
 
-    for (i, bezier) in beziers.iter().enumerate() {
-        let mut any_intersections = false;
+const kCellInlineBeziers: usize = 8;
 
-        // Every vertical grid line including edges
-        for x in 0..=grid_width {
-            let int_y = bezier.intersect_vert((x as f32) * glyph_size.x / grid_width as f32);
-            for y in int_y.iter() {
-                let y = y * grid_height as f32 / glyph_size.y;
-                setgrid(x as f32, y, i); // right
-                setgrid(x as f32 - 1.0, y, i); // left
-                any_intersections = true;
+impl CellBeziers {
+    fn insert(&mut self, index: u32) {
+        if !self.spilled.is_empty() {
+            if let Err(i) = self.spilled.binary_search(&index) {
+                self.spilled.insert(i, index);
             }
+            return;
+        }
+
+        let len = self.len;
+        match self.inline[..len].binary_search(&index) {
+            Ok(_) => {}
+            Err(i) if len < kCellInlineBeziers => {
+                self.inline.copy_within(i..len, i + 1);
+                self.inline[i] = index;
+                self.len += 1;
+            }
+            Err(i) => {
+                let mut spilled = Vec::with_capacity(kCellInlineBeziers * 2);
+                spilled.extend_from_slice(&self.inline[..i]);
+                spilled.push(index);
+                spilled.extend_from_slice(&self.inline[i..]);
+                self.spilled = spilled;
+            }
+        }
+    }
+
+    fn as_slice(&self) -> &[u32] {
+        if self.spilled.is_empty() {
+            &self.inline[..self.len]
+        } else {
+            &self.spilled
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+}
+
+impl<'a> IntoIterator for &'a CellBeziers {
+    type Item = &'a u32;
+    type IntoIter = std::slice::Iter<'a, u32>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl FromIterator<u32> for CellBeziers {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut cell = CellBeziers::default();
+        for index in iter {
+            cell.insert(index);
+        }
+        cell
+    }
+}
+
+// find_cells_mids_inside skips beziers for cell midlines further than
+// this (in grid cells) outside the bounds of their control points. Such
+// lines can't cross the curve, and the margin is far larger than any
+// rounding error near the bounds.
+const kBoundsMargin: f32 = 1.0;
+
+// Parameter in (0, 1) where the derivative of a quadratic bezier
+// coordinate with control values p0, p1, p2 is zero, if any
+fn extremum_t(p0: f32, p1: f32, p2: f32) -> Option<f32> {
+    let denom = p0 - 2.0 * p1 + p2;
+    if denom == 0.0 {
+        return None;
+    }
+    let t = (p0 - p1) / denom;
+    if t > 0.0 && t < 1.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+// Parameter between t0 and t1 where the curve crosses the horizontal
+// line at y. The curve must be monotonic in y between them.
+fn crossing_t(bezier: &Bezier2, t0: f32, t1: f32, y: f32) -> f32 {
+    let rising = bezier.point_at(t1).y >= bezier.point_at(t0).y;
+    let (mut a, mut b) = (t0, t1);
+    for _ in 0..32 {
+        let mid = (a + b) / 2.0;
+        if (bezier.point_at(mid).y < y) == rising {
+            a = mid;
+        } else {
+            b = mid;
         }
+    }
+    (a + b) / 2.0
+}
 
-        // Every horizontal grid line including edges
-        for y in 0..=grid_height {
-            let int_x = bezier.intersect_horz((y as f32) * glyph_size.y / grid_height as f32);
-            for x in int_x.iter() {
-                let x = x * grid_width as f32 / glyph_size.x;
-                setgrid(x, y as f32, i); // right
-                setgrid(x, y as f32 - 1.0, i); // left
-                any_intersections = true;
+// Calls `cross` with the x and direction (1 upwards, -1 downwards) of
+// every crossing of the curve with the horizontal line at y. The curve
+// is split at its y extremum, and each piece crosses when its ends are
+// on different sides of the line, an end exactly on it counting as
+// below. So a curve touching the line, or two curves meeting on it,
+// cross it once or not at all, never twice.
+fn for_each_crossing(bezier: &Bezier2, y: f32, mut cross: impl FnMut(f32, i32)) {
+    let mut ts = [0.0, 1.0, 1.0];
+    let n = match extremum_t(bezier.e0.y, bezier.c.y, bezier.e1.y) {
+        Some(t) => {
+            ts[1] = t;
+            2
+        }
+        None => 1,
+    };
+    for piece in ts[..=n].windows(2) {
+        let y0 = bezier.point_at(piece[0]).y;
+        let y1 = bezier.point_at(piece[1]).y;
+        if (y0 <= y) != (y1 <= y) {
+            let t = crossing_t(bezier, piece[0], piece[1], y);
+            cross(bezier.point_at(t).x, if y1 > y0 { 1 } else { -1 });
+        }
+    }
+}
+
+fn scale_bezier(bezier: &Bezier2, scale_x: f32, scale_y: f32) -> Bezier2 {
+    Bezier2::from(
+        bezier.e0.x * scale_x,
+        bezier.e0.y * scale_y,
+        bezier.e1.x * scale_x,
+        bezier.e1.y * scale_y,
+        bezier.c.x * scale_x,
+        bezier.c.y * scale_y,
+    )
+}
+
+// Calls `mark` with every cell of a width x height grid that the curve
+// passes through or touches. The curve must already be in grid units.
+// It's split at its extrema into pieces that are monotonic in x and y,
+// and those at the horizontal grid lines they cross. Each resulting
+// segment stays within one row, where it covers every column between
+// the x of its ends. Points on a grid line touch the cells on both
+// sides of it.
+fn for_each_bezier_cell(bezier: &Bezier2, width: u32, height: u32, mut mark: impl FnMut(u32, u32)) {
+    // Cells whose closed extent overlaps [lo, hi]
+    let span = |lo: f32, hi: f32, cells: u32| {
+        let first = (lo.ceil() - 1.0).clamp(0.0, cells as f32 - 1.0) as u32;
+        let last = hi.floor().clamp(0.0, cells as f32 - 1.0) as u32;
+        first..=last.max(first)
+    };
+    let mut mark_segment = |x0: f32, x1: f32, y0: f32, y1: f32| {
+        let rows = if y0 == y1 {
+            span(y0, y1, height)
+        } else {
+            let row = ((y0 + y1) / 2.0).floor().clamp(0.0, height as f32 - 1.0) as u32;
+            row..=row
+        };
+        for y in rows {
+            for x in span(x0.min(x1), x0.max(x1), width) {
+                mark(x, y);
             }
         }
+        // The ends may lie on grid lines or corners, touching cells
+        // outside the segment's own row
+        for &(x, y) in [(x0, y0), (x1, y1)].iter() {
+            for y in span(y, y, height) {
+                for x in span(x, x, width) {
+                    mark(x, y);
+                }
+            }
+        }
+    };
 
-        // If no grid line intersections, bezier is fully contained in
-        // one cell. Mark this bezier as intersecting that cell.
-        if !any_intersections {
-            let x = beziers[i].e0.x * grid_width as f32 / glyph_size.x;
-            let y = beziers[i].e0.y * grid_height as f32 / glyph_size.y;
-            setgrid(x, y, i);
+    let (e0, c, e1) = (bezier.e0, bezier.c, bezier.e1);
+    let mut ts = [0.0, 1.0, 1.0, 1.0];
+    let mut n = 1;
+    for t in [extremum_t(e0.x, c.x, e1.x), extremum_t(e0.y, c.y, e1.y)]
+        .iter()
+        .flatten()
+    {
+        ts[n] = *t;
+        n += 1;
+    }
+    ts[1..n].sort_by(|a, b| a.total_cmp(b));
+    ts[n] = 1.0;
+
+    for piece in ts[..=n].windows(2) {
+        let (t_start, t_end) = (piece[0], piece[1]);
+        let start = bezier.point_at(t_start);
+        let end = bezier.point_at(t_end);
+
+        // Horizontal lines strictly between the ends, in the order the
+        // piece crosses them
+        let rising = end.y >= start.y;
+        let (lo, hi) = (start.y.min(end.y), start.y.max(end.y));
+        let first_line = (lo.floor() + 1.0).max(1.0);
+        let last_line = (hi.ceil() - 1.0).min(height as f32 - 1.0);
+        let lines = (last_line - first_line + 1.0).max(0.0) as u32;
+
+        let (mut t0, mut x0, mut y0) = (t_start, start.x, start.y);
+        for i in 0..lines {
+            let line = if rising {
+                first_line + i as f32
+            } else {
+                last_line - i as f32
+            };
+
+            let t = crossing_t(bezier, t0, t_end, line);
+            let x = bezier.point_at(t).x;
+            mark_segment(x0, x, y0, line);
+            t0 = t;
+            x0 = x;
+            y0 = line;
         }
+        mark_segment(x0, end.x, y0, end.y);
+    }
+}
+
+fn find_cells_intersections(
+    beziers: &Vec<Bezier2>,
+    glyph_size: &Vec2,
+    grid_width: u32,
+    grid_height: u32,
+) -> Vec<CellBeziers> {
+    let mut ret = vec![CellBeziers::default(); (grid_width * grid_height) as usize];
+
+    let scale_x = grid_width as f32 / glyph_size.x;
+    let scale_y = grid_height as f32 / glyph_size.y;
+    for (i, bezier) in beziers.iter().enumerate() {
+        let scaled = scale_bezier(bezier, scale_x, scale_y);
+        for_each_bezier_cell(&scaled, grid_width, grid_height, |x, y| {
+            ret[(y * grid_width + x) as usize].insert(i as u32);
+        });
     }
 
     ret
@@ -103,62 +396,113 @@ fn find_cells_mids_inside(
     grid_width: u32,
     grid_height: u32,
 ) -> Vec<bool> {
-    let mut cell_mids = Vec::new();
-    cell_mids.resize((grid_width * grid_height) as usize, false);
+    let mut cell_mids = vec![false; (grid_width * grid_height) as usize];
+
+    let scale_x = grid_width as f32 / glyph_size.x;
+    let scale_y = grid_height as f32 / glyph_size.y;
+    let beziers: Vec<Bezier2> = beziers
+        .iter()
+        .map(|b| scale_bezier(b, scale_x, scale_y))
+        .collect();
+
+    // Sweep the rows from the bottom up, keeping a list of the beziers
+    // whose bounds reach the current row's midpoint line
+    let bounds: Vec<(f32, f32)> = beziers
+        .iter()
+        .map(|b| {
+            let (min, max) = b.bounds();
+            (min.y - kBoundsMargin, max.y + kBoundsMargin)
+        })
+        .collect();
+    let mut by_bottom: Vec<usize> = (0..beziers.len()).collect();
+    by_bottom.sort_by(|a, b| bounds[*a].0.total_cmp(&bounds[*b].0));
+    let mut by_bottom = by_bottom.into_iter().peekable();
+    let mut active: Vec<usize> = Vec::new();
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
 
-    // // Find whether the center of each cell is inside the glyph
     for y in 0..grid_height {
-        // Find all intersections with cells horizontal midpoint line
-        // and store them sorted from left to right
-        let mut intersections = HashSet::new();
-        let y_mid = y as f32 + 0.5;
-        for b in beziers.iter() {
-            // 		float intX[2];
-            let int_x = b.intersect_horz(y_mid * glyph_size.y as f32 / grid_height as f32);
-            for x in int_x.iter() {
-                let x = x * grid_width as f32 / glyph_size.x;
-                intersections.insert(OrderedFloat(x));
-            }
-        }
-        let mut intersections = intersections.into_iter().collect::<Vec<_>>();
-        intersections.sort();
-
-        // Traverse intersections (whole grid row, left to right).
-        // Every 2nd crossing represents exiting an "inside" region.
-        // All properly formed glyphs should have an even number of
-        // crossings.
-        let mut outside = false;
-        let mut start: f32 = 0.0;
-        for end in intersections.iter() {
-            let end = end.0;
-
-            // println!("{}", end);
-            // Upon exiting, the midpoint of every cell between
-            // start and end, rounded to the nearest int, is
-            // inside the glyph.
-            if outside {
-                // let startCell = clamp((int)std::round(start), 0, gridWidth);
-                // int endCell = clamp((int)std::round(end), 0, gridWidth);
-                let start_cell = start.round().clamp(0.0, grid_width as f32) as usize;
-                let end_cell = end.round().clamp(0.0, grid_width as f32) as usize;
-                // println!("{}, {}", start_cell, end_cell);
-                for x in start_cell..end_cell {
-                    cell_mids[((y * grid_width) as usize + x) as usize] = true;
-                }
-            }
+        let line = y as f32 + 0.5;
+        while let Some(i) = by_bottom.next_if(|i| bounds[*i].0 <= line) {
+            active.push(i);
+        }
+        active.retain(|i| bounds[*i].1 >= line);
 
-            outside = !outside;
-            start = end;
+        crossings.clear();
+        for b in active.iter() {
+            for_each_crossing(&beziers[*b], line, |x, dir| crossings.push((x, dir)));
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // The winding number of each cell center is the sum of the
+        // directions of the crossings left of it. The glyph's inside is
+        // wherever it's nonzero.
+        let mut winding = 0;
+        let mut pending = crossings.iter().peekable();
+        for x in 0..grid_width {
+            let mid = x as f32 + 0.5;
+            while let Some((_, dir)) = pending.next_if(|c| c.0 < mid) {
+                winding += dir;
+            }
+            cell_mids[(y * grid_width + x) as usize] = winding != 0;
         }
     }
 
     cell_mids
 }
 
+// Picks the grid size for a glyph. The grid follows the glyph's aspect
+// ratio and is the smallest one in which no cell intersects more than
+// `max_cell_beziers` beziers, so simple glyphs take little atlas space
+// and complex ones get finer cells. Neither side exceeds `max_size`; if
+// even that grid is too coarse, the max_size grid is used.
+fn choose_grid_size(
+    curves: &Vec<Bezier2>,
+    glyph_size: &Vec2,
+    max_size: u8,
+    max_cell_beziers: usize,
+) -> (u8, u8) {
+    let max_size = max_size.max(1) as f32;
+    let aspect = glyph_size.x / glyph_size.y;
+    let size_for = |n: f32| -> (u8, u8) {
+        let (w, h) = if aspect >= 1.0 {
+            (n, n / aspect)
+        } else {
+            (n * aspect, n)
+        };
+        (
+            w.round().clamp(1.0, max_size) as u8,
+            h.round().clamp(1.0, max_size) as u8,
+        )
+    };
+
+    // Every bezier lies in at least one cell, so fewer cells than this
+    // can't work
+    let min_cells = (curves.len() as f32 / max_cell_beziers.max(1) as f32).ceil();
+    let mut n = 1.0;
+    while n < max_size {
+        let (w, h) = size_for(n);
+        if w as f32 * h as f32 >= min_cells {
+            break;
+        }
+        n += 1.0;
+    }
+
+    while n < max_size {
+        let (w, h) = size_for(n);
+        let cells = find_cells_intersections(curves, glyph_size, w as u32, h as u32);
+        if cells.iter().all(|c| c.len() <= max_cell_beziers) {
+            return (w, h);
+        }
+        n += 1.0;
+    }
+
+    size_for(max_size)
+}
+
 struct VGrid {
     width: u16,
     height: u16,
-    cellBeziers: Vec<HashSet<usize>>,
+    cellBeziers: Vec<CellBeziers>,
     cellMids: Vec<bool>,
 }
 
@@ -174,7 +518,13 @@ impl VGrid {
         }
     }
 
-    fn write_vgrid_at(&self, at_x: u16, at_y: u16, data: &mut [u8]) {
+    fn write_vgrid_at(
+        &self,
+        at_x: u16,
+        at_y: u16,
+        data: &mut [u8],
+        index_width: IndexWidth,
+    ) -> Result<(), AtlasError> {
         self._write_vgrid_at(
             at_x,
             at_y,
@@ -182,9 +532,16 @@ impl VGrid {
             kGridAtlasSize,
             kGridAtlasSize,
             kAtlasChannels,
+            kGridAtlasLayers,
+            index_width,
         )
     }
 
+    // The atlas consists of `layers` images of width x height texels
+    // stacked vertically. A cell's first four bezier indices are in the
+    // first layer, any further ones at the same position in the next.
+    // Wide indices are stored in native byte order, as GL expects.
+    #[allow(clippy::too_many_arguments)]
     fn _write_vgrid_at(
         &self,
         at_x: u16,
@@ -193,30 +550,94 @@ impl VGrid {
         width: u16,
         height: u16,
         depth: u8,
-    ) {
+        layers: u8,
+        index_width: IndexWidth,
+    ) -> Result<(), AtlasError> {
         assert!((at_x + self.width) <= width);
         assert!((at_y + self.height) <= height);
 
+        // Indices are never truncated to fit the texels
+        let beziers = self
+            .cellBeziers
+            .iter()
+            .flatten()
+            .max()
+            .map_or(0, |i| *i as usize + 1);
+        if beziers > index_width.max_beziers() {
+            return Err(AtlasError::TooManyBeziers {
+                beziers,
+                max: index_width.max_beziers(),
+            });
+        }
+
+        let capacity = depth as usize * layers as usize;
+        for (cell, beziers) in self.cellBeziers.iter().enumerate() {
+            if beziers.len() > capacity {
+                return Err(AtlasError::TooManyBeziersInCell {
+                    cell,
+                    beziers: beziers.len(),
+                });
+            }
+        }
+
+        let texel_bytes = depth as usize * index_width.bytes();
+        let layer_size = xy2i(0, height, width) * texel_bytes;
+        let mut texels = vec![0_u16; capacity];
         for y in 0..self.height {
             for x in 0..self.width {
                 let cell_idx = xy2i(x, y, self.width);
-                let atlas_idx = xy2i(at_x + x, at_y + y, width) * depth as usize;
+                let atlas_idx = xy2i(at_x + x, at_y + y, width) * texel_bytes;
 
-                let beziers = &self.cellBeziers[cell_idx];
-                if beziers.len() > depth as usize {
-                    panic!("WARN: Too many beziers in one grid cell")
-                }
-                for i in 0..depth {
-                    data[atlas_idx + i as usize] = 100;
-                }
                 write_vgrid_cell_to_buffer(
                     &self.cellBeziers,
                     &self.cellMids,
                     cell_idx,
-                    &mut data[atlas_idx..atlas_idx + depth as usize],
+                    &mut texels,
+                    depth as usize,
                 );
+                for (layer, texel) in texels.chunks(depth as usize).enumerate() {
+                    let i = atlas_idx + layer * layer_size;
+                    let out = &mut data[i..i + texel_bytes];
+                    match index_width {
+                        IndexWidth::Narrow => {
+                            for (b, index) in out.iter_mut().zip(texel) {
+                                *b = *index as u8;
+                            }
+                        }
+                        IndexWidth::Wide => {
+                            for (b, index) in out.chunks_mut(2).zip(texel) {
+                                b.copy_from_slice(&index.to_ne_bytes());
+                            }
+                        }
+                    }
+                }
             }
         }
+
+        Ok(())
+    }
+}
+
+// Copies a glyph's grid, all layers of it, between grid atlases of the
+// same index width
+fn copy_grid(
+    from: &[u8],
+    to: &mut [u8],
+    from_pos: [u16; 2],
+    to_pos: [u16; 2],
+    size: [u16; 2],
+    index_width: IndexWidth,
+) {
+    let depth = kAtlasChannels as usize * index_width.bytes();
+    let layer_size = xy2i(0, kGridAtlasSize, kGridAtlasSize) * depth;
+    let row_len = size[0] as usize * depth;
+    for layer in 0..kGridAtlasLayers as usize {
+        for y in 0..size[1] {
+            let src =
+                xy2i(from_pos[0], from_pos[1] + y, kGridAtlasSize) * depth + layer * layer_size;
+            let dst = xy2i(to_pos[0], to_pos[1] + y, kGridAtlasSize) * depth + layer * layer_size;
+            to[dst..dst + row_len].copy_from_slice(&from[src..src + row_len]);
+        }
     }
 }
 
@@ -224,32 +645,41 @@ fn xy2i(x: u16, y: u16, w: u16) -> usize {
     (y as usize * w as usize) + x as usize
 }
 
+// Writes the bezier indices of a cell into `data`, which holds one texel
+// of `depth` channels per atlas layer.
 fn write_vgrid_cell_to_buffer(
-    cell_beziers: &Vec<HashSet<usize>>,
+    cell_beziers: &Vec<CellBeziers>,
     cell_mids: &Vec<bool>,
     cell_idx: usize,
-    data: &mut [u8],
+    data: &mut [u16],
+    depth: usize,
 ) {
-    let beziers = &cell_beziers[cell_idx];
-    let mut beziers: Vec<&usize> = beziers.into_iter().collect();
-    beziers.sort();
+    let beziers = cell_beziers[cell_idx].as_slice();
 
-    // Clear texel
+    // Clear texels
     for item in data.iter_mut() {
         *item = kBezierIndexUnused;
     }
 
-    // Write out bezier indices to atlas texel
+    // Write out bezier indices to atlas texels
     let mut i: usize = 0;
     let nbeziers = min(beziers.len(), data.len());
     for it in beziers.iter().take(nbeziers) {
-        // TODO: The uint8_t cast wont overflow because the bezier
-        // limit is checked when loading the glyph. But try to encode
-        // that info into the data types so no cast is needed.
-        data[i] = **it as u8 + kBezierIndexFirstReal;
+        // The cast won't overflow because _write_vgrid_at checks the
+        // bezier count against the index width first
+        data[i] = *it as u16 + kBezierIndexFirstReal;
         i += 1;
     }
 
+    // Indices that don't fit in one texel continue in the next layer.
+    // Like mid_inside below, this is flagged by the order of the
+    // indices: data[2] > data[3] means there are more. A texel with
+    // fewer than four indices ends in kBezierIndexUnused, so data[3]
+    // must also be a real index for the flag to be set.
+    for texel in 1..nbeziers.div_ceil(depth) {
+        data.swap(texel * depth - 2, texel * depth - 1);
+    }
+
     let mid_inside = cell_mids[cell_idx];
 
     // Because the order of beziers doesn't matter and a single bezier is
@@ -257,7 +687,7 @@ fn write_vgrid_cell_to_buffer(
     // adjusting the order of the bezier indices. In this case, the
     // midInside bit is 1 if data[0] > data[1].
     // Note that the bezier indices are already sorted from smallest to
-    // largest because of std::set.
+    // largest because CellBeziers keeps them sorted.
     if mid_inside {
         // If cell is empty, there's nothing to swap (both values 0).
         // So a fake "sort meta" value must be used to make data[0]
@@ -281,121 +711,17 @@ fn write_vgrid_cell_to_buffer(
     }
 }
 
-fn insert_curves(
-    verts: &mut Vec<GlVertex>,
-    curves: &Vec<Bezier2>,
-    glyphDataBuf: &mut [u16],
-    gridAtlas: &mut [u8],
-) {
-    // this->glyphs.resize(text.size());
-
-    // GlyphVertex emptyVert{};
-    // this->verts.insert(this->verts.begin() + index * 6, text.size() * 6, emptyVert);
-
-    // glm::vec2 appendOffset(0, 0);
-
-    let i = 0;
-    let index = 0;
-    let color: [f32; 4] = [0.5, 0.0, 0.0, 1.0];
-
-    // for (size_t i = 0; i < text.size(); i++)
-    // {
-    let glyph = get_glyph_for_codepoint(&curves, glyphDataBuf, gridAtlas);
-
-    let mut vs = [GlVertex::default(); 6]; // Insertion code depends on v[0] equaling appendOffset (therefore it is also set before continue;s above)
-    vs[0].pos = Vec2::from(0.0, 0.0);
-    vs[1].pos = Vec2::from(glyph.size[0] as f32, 0.0);
-    vs[2].pos = Vec2::from(0.0, glyph.size[1] as f32);
-    vs[3].pos = Vec2::from(glyph.size[0] as f32, glyph.size[1] as f32);
-    vs[4].pos = Vec2::from(0.0, glyph.size[1] as f32);
-    vs[5].pos = Vec2::from(glyph.size[0] as f32, 0.0);
-    for (j, mut v) in vs.iter_mut().enumerate() {
-        // 		v[j].pos += appendOffset;
-        v.pos.x += glyph.offset[0] as f32;
-        v.pos.y += glyph.offset[1] as f32;
-
-        v.color = [
-            (color[0] * 255.0) as u8,
-            (color[1] * 255.0) as u8,
-            (color[2] * 255.0) as u8,
-            (color[3] * 255.0) as u8,
-        ];
-
-        // 		// Encode both the bezier position and the norm coord into one int
-        // 		// This theoretically could overflow, but the atlas position will
-        // 		// never be over half the size of a uint16, so it's fine.
-        let k = (if j < 4 { j } else { 6 - j }) as u32;
-        let normX = k & 1;
-        let normY = if k > 1 { 1_u32 } else { 0_u32 };
-        let norm = (normX << 1) + normY;
-        v.data = ((glyph.bezierAtlasPos[0] as u32) << 2) + norm;
-        // verts[(index + i) * 6 + j] = v.clone();
-        verts.push(v.clone());
-    }
-
-    // 	appendOffset.x += glyph->advance;
-    // 	this->glyphs[index + i] = glyph;
-    // }
-}
-
-fn get_glyph_for_codepoint(
-    curves: &Vec<Bezier2>,
-    glyphDataBuf: &mut [u16],
-    gridAtlas: &mut [u8],
-) -> Glyph {
-    // AtlasGroup *atlas = this->GetOpenAtlasGroup();
-
-    let glyph_width = 1398;
-    let glyph_height = 1450;
-
-    let hori_bearing_x = 97;
-    let hori_bearing_y = 1430;
-    let hori_advance = 1593;
-
-    let grid_width = kGridMaxSize;
-    let grid_height = kGridMaxSize;
-    let glyph_size = Vec2::from(glyph_width as f32, glyph_height as f32);
-
-    let grid = VGrid::from(&curves, &glyph_size, grid_width as u32, grid_height as u32);
-
-    // Although the data is represented as a 32bit texture, it's actually
-    // two 16bit ints per pixel, each with an x and y coordinate for
-    // the bezier. Every six 16bit ints (3 pixels) is a full bezier
-    // Plus two pixels for grid position information
-    let bezierPixelLength = 2 + curves.len() * 3;
-
-    // uint8_t *bezierData = atlas->glyphDataBuf + (atlas->glyphDataBufOffset * kAtlasChannels);
-
-    write_glyph_data_to_buffer(
-        glyphDataBuf,
-        &curves,
-        &glyph_size,
-        0,
-        0,
-        kGridMaxSize as u16,
-        kGridMaxSize as u16,
-    );
-
-    // // TODO: Integrate with AtlasGroup / replace AtlasGroup
-    // WriteVGridAt(grid, atlas->nextGridPos[0], atlas->nextGridPos[1], atlas->gridAtlas, kGridAtlasSize, kGridAtlasSize, kAtlasChannels);
-    grid.write_vgrid_at(0, 0, gridAtlas);
-
-    let mut glyph = Glyph::default();
-    glyph.bezierAtlasPos[0] = 0;
-    glyph.bezierAtlasPos[1] = 0;
-    glyph.size[0] = glyph_width;
-    glyph.size[1] = glyph_height;
-    glyph.offset[0] = hori_bearing_x;
-    glyph.offset[1] = hori_bearing_y as i16 - glyph_height as i16;
-    glyph.advance = hori_advance;
-
-    glyph
-}
-
 pub struct Grid {
     pub verts: Vec<GlVertex>,
-    glyph_data_buf: Vec<u16>,
-    grid_atlas: Vec<u8>,
+    glyphs: Vec<usize>, // Entry drawn by each six verts
+    entries: Vec<Option<CacheEntry>>,
+    keys: HashMap<u32, usize>,
+    tick: u64, // Bumped whenever a glyph is used, for LRU eviction
+    evictions: usize,
+    pub atlases: Vec<AtlasGroup>,
+    pub max_grid_size: u8,       // Largest grid side used for a glyph
+    pub max_atlas_pages: usize,  // Pages opened before evicting glyphs
+    pub index_width: IndexWidth, // Of new pages
     pub vertex_array_id: u32,
     pub prog_id: u32,
 
@@ -427,28 +753,475 @@ unsafe fn vec_as_u8_slice<T: Sized>(p: Vec<T>) -> &'static [u8] {
 }
 
 impl Grid {
-    pub fn atlas_ptr(&self) -> *const std::ffi::c_void {
-        self.grid_atlas.as_ptr() as *const std::os::raw::c_void
+    // Writes a new glyph to the atlases and appends a quad drawing it.
+    // The glyph isn't cached, so its atlas space is freed as soon as the
+    // quad is removed.
+    pub fn insert_curves(
+        &mut self,
+        curves: &Vec<Bezier2>,
+        color: [f32; 4],
+    ) -> Result<(), AtlasError> {
+        let entry = self.get_glyph_for_codepoint(curves)?;
+        let entry = self.add_entry(entry);
+        self.push_quad(entry, color);
+        Ok(())
+    }
+
+    // Appends a quad drawing the glyph cached under `key`, writing it to
+    // the atlases from `curves` if it isn't cached yet. Cached glyphs stay
+    // in the atlases after their last quad is removed, until the space is
+    // needed for another glyph.
+    pub fn insert_glyph(
+        &mut self,
+        key: u32,
+        curves: &Vec<Bezier2>,
+        color: [f32; 4],
+    ) -> Result<(), AtlasError> {
+        let entry = match self.keys.get(&key) {
+            Some(&entry) => entry,
+            None => {
+                let mut entry = self.get_glyph_for_codepoint(curves)?;
+                entry.key = Some(key);
+                let entry = self.add_entry(entry);
+                self.keys.insert(key, entry);
+                entry
+            }
+        };
+        self.push_quad(entry, color);
+        Ok(())
+    }
+
+    // Removes the quads of a range of glyphs, releasing their references
+    // to the cached glyphs. Later glyphs move down to fill the gap.
+    pub fn remove_glyphs(&mut self, range: Range<usize>) {
+        self.verts.drain(range.start * 6..range.end * 6);
+        for entry in self.glyphs.drain(range).collect::<Vec<_>>() {
+            let cached = self.entries[entry].as_mut().unwrap();
+            cached.refs -= 1;
+            if cached.refs == 0 && cached.key.is_none() {
+                self.free_entry(entry);
+            }
+        }
+    }
+
+    // Repacks every cached glyph into fresh pages, tallest grids first,
+    // so space left between glyphs by eviction can hold large glyphs
+    // again. The grid's own verts are re-pointed, and the returned table
+    // lists every glyph that moved so copies of them can be too. All
+    // pages need uploading again afterwards. If the glyphs would need
+    // more than `max_atlas_pages` pages, nothing changes and AtlasFull is
+    // returned.
+    pub fn compact(&mut self) -> Result<Vec<Relocation>, AtlasError> {
+        let mut order: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].is_some())
+            .collect();
+        order.sort_by_key(|&i| {
+            let entry = self.entries[i].as_ref().unwrap();
+            Reverse((entry.grid_size[1], entry.grid_size[0], entry.data_len))
+        });
+
+        // Place every glyph before moving any, so running out of pages
+        // leaves the grid as it was
+        let mut atlases: Vec<AtlasGroup> = Vec::new();
+        let mut placements = Vec::with_capacity(order.len());
+        for &i in order.iter() {
+            let entry = self.entries[i].as_ref().unwrap();
+            let [width, height] = entry.grid_size;
+            let index_width = self.atlases[entry.glyph.bezierAtlasPos[1] as usize].index_width;
+
+            // Glyphs stay on pages with the same index width
+            let reserved = atlases
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, a)| a.index_width == index_width)
+                .find_map(|(page, a)| a.reserve(width, height, entry.data_len).map(|r| (page, r)));
+            let placement = match reserved {
+                Some(reserved) => reserved,
+                None => {
+                    if atlases.len() >= self.max_atlas_pages {
+                        return Err(AtlasError::AtlasFull {
+                            pages: self.max_atlas_pages,
+                        });
+                    }
+                    let mut atlas = AtlasGroup::new(index_width);
+                    let reserved = atlas
+                        .reserve(width, height, entry.data_len)
+                        .expect("A cached glyph fits on an empty page");
+                    atlases.push(atlas);
+                    (atlases.len() - 1, reserved)
+                }
+            };
+            placements.push(placement);
+        }
+
+        let old_atlases = std::mem::replace(&mut self.atlases, atlases);
+        let mut relocations = Vec::new();
+        for (i, (page, (grid_pos, data_offset))) in order.into_iter().zip(placements) {
+            let entry = self.entries[i].as_mut().unwrap();
+            let [old_offset, old_page] = entry.glyph.bezierAtlasPos;
+            let from = &old_atlases[old_page as usize];
+            let to = &mut self.atlases[page];
+            copy_grid(
+                &from.grid_atlas,
+                &mut to.grid_atlas,
+                entry.grid_pos,
+                grid_pos,
+                entry.grid_size,
+                from.index_width,
+            );
+
+            let channels = (kAtlasChannels / 2) as usize;
+            let from_start = old_offset as usize * channels;
+            let to_start = data_offset as usize * channels;
+            let len = entry.data_len * channels;
+            to.glyph_data_buf[to_start..to_start + len]
+                .copy_from_slice(&from.glyph_data_buf[from_start..from_start + len]);
+            to.glyph_data_buf[to_start] = grid_pos[0];
+            to.glyph_data_buf[to_start + 1] = grid_pos[1];
+
+            entry.grid_pos = grid_pos;
+            entry.glyph.bezierAtlasPos = [data_offset as u16, page as u16];
+            if entry.glyph.bezierAtlasPos != [old_offset, old_page] {
+                relocations.push(Relocation {
+                    old_page,
+                    old_offset,
+                    new_page: page as u16,
+                    new_offset: data_offset as u16,
+                });
+            }
+        }
+
+        for atlas in self.atlases.iter_mut() {
+            atlas.uploaded = false;
+        }
+        for (quad, &entry) in self.verts.chunks_mut(6).zip(self.glyphs.iter()) {
+            let offset = self.entries[entry].as_ref().unwrap().glyph.bezierAtlasPos[0] as u32;
+            for v in quad.iter_mut() {
+                v.data = (offset << 2) + (v.data & 3);
+            }
+        }
+
+        Ok(relocations)
+    }
+
+    pub fn stats(&self) -> AtlasStats {
+        let mut stats = AtlasStats {
+            pages: self.atlases.len(),
+            evictions: self.evictions,
+            ..AtlasStats::default()
+        };
+        for atlas in self.atlases.iter() {
+            stats.grid_cells_used += atlas.grid_packer.used();
+            stats.grid_cells_total += kGridAtlasSize as usize * kGridAtlasSize as usize;
+            stats.glyph_data_used += atlas.glyph_data_alloc.used();
+            stats.glyph_data_total += atlas.glyph_data_alloc.capacity();
+        }
+        for entry in self.entries.iter().flatten() {
+            if entry.key.is_some() {
+                stats.cached_glyphs += 1;
+                if entry.refs > 0 {
+                    stats.referenced_glyphs += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    fn glyph(&self, index: usize) -> &Glyph {
+        &self.entries[self.glyphs[index]].as_ref().unwrap().glyph
+    }
+
+    fn add_entry(&mut self, entry: CacheEntry) -> usize {
+        match self.entries.iter().position(|e| e.is_none()) {
+            Some(i) => {
+                self.entries[i] = Some(entry);
+                i
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        }
+    }
+
+    fn free_entry(&mut self, entry: usize) {
+        let entry = self.entries[entry].take().unwrap();
+        if let Some(key) = entry.key {
+            self.keys.remove(&key);
+        }
+        let page = entry.glyph.bezierAtlasPos[1] as usize;
+        let data_offset = entry.glyph.bezierAtlasPos[0] as u32;
+        self.atlases[page].release(
+            entry.grid_pos,
+            entry.grid_size,
+            (data_offset, entry.data_len),
+        );
+    }
+
+    // Evicts the least recently used cached glyphs that no quad draws
+    // from the page where the fewest of them make room for a glyph. If no
+    // page would have room with all of them gone, nothing is evicted and
+    // AtlasFull is returned, as only compact() could make room.
+    fn evict_to_fit(
+        &mut self,
+        grid_width: u16,
+        grid_height: u16,
+        data_len: usize,
+        beziers: usize,
+    ) -> Result<(), AtlasError> {
+        let mut unused: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].as_ref().is_some_and(|e| e.refs == 0))
+            .collect();
+        unused.sort_by_key(|&i| self.entries[i].as_ref().unwrap().last_used);
+
+        let mut victims: Option<Vec<usize>> = None;
+        for (page, atlas) in self.atlases.iter().enumerate() {
+            if beziers > atlas.index_width.max_beziers() {
+                continue;
+            }
+            let on_page: Vec<usize> = unused
+                .iter()
+                .copied()
+                .filter(|&i| {
+                    self.entries[i].as_ref().unwrap().glyph.bezierAtlasPos[1] as usize == page
+                })
+                .collect();
+            let releases: Vec<_> = on_page
+                .iter()
+                .map(|&i| {
+                    let e = self.entries[i].as_ref().unwrap();
+                    let data = (e.glyph.bezierAtlasPos[0] as u32, e.data_len);
+                    (e.grid_pos, e.grid_size, data)
+                })
+                .collect();
+            if let Some(n) = atlas.releases_to_fit(&releases, grid_width, grid_height, data_len) {
+                if n > 0 && victims.as_ref().is_none_or(|v| n < v.len()) {
+                    victims = Some(on_page[..n].to_vec());
+                }
+            }
+        }
+
+        match victims {
+            Some(victims) => {
+                for entry in victims {
+                    self.free_entry(entry);
+                    self.evictions += 1;
+                }
+                Ok(())
+            }
+            None => Err(AtlasError::AtlasFull {
+                pages: self.atlases.len(),
+            }),
+        }
+    }
+
+    fn push_quad(&mut self, entry: usize, color: [f32; 4]) {
+        self.tick += 1;
+        let cached = self.entries[entry].as_mut().unwrap();
+        cached.refs += 1;
+        cached.last_used = self.tick;
+        let glyph = &cached.glyph;
+
+        let mut vs = [GlVertex::default(); 6]; // Insertion code depends on v[0] equaling appendOffset (therefore it is also set before continue;s above)
+        vs[0].pos = Vec2::from(0.0, 0.0);
+        vs[1].pos = Vec2::from(glyph.size[0] as f32, 0.0);
+        vs[2].pos = Vec2::from(0.0, glyph.size[1] as f32);
+        vs[3].pos = Vec2::from(glyph.size[0] as f32, glyph.size[1] as f32);
+        vs[4].pos = Vec2::from(0.0, glyph.size[1] as f32);
+        vs[5].pos = Vec2::from(glyph.size[0] as f32, 0.0);
+        for (j, mut v) in vs.iter_mut().enumerate() {
+            // 		v[j].pos += appendOffset;
+            v.pos.x += glyph.offset[0] as f32;
+            v.pos.y += glyph.offset[1] as f32;
+
+            v.color = [
+                (color[0] * 255.0) as u8,
+                (color[1] * 255.0) as u8,
+                (color[2] * 255.0) as u8,
+                (color[3] * 255.0) as u8,
+            ];
+
+            // 		// Encode both the bezier position and the norm coord into one int
+            // 		// This theoretically could overflow, but the atlas position will
+            // 		// never be over half the size of a uint16, so it's fine.
+            let k = (if j < 4 { j } else { 6 - j }) as u32;
+            let normX = k & 1;
+            let normY = if k > 1 { 1_u32 } else { 0_u32 };
+            let norm = (normX << 1) + normY;
+            v.data = ((glyph.bezierAtlasPos[0] as u32) << 2) + norm;
+            // verts[(index + i) * 6 + j] = v.clone();
+            self.verts.push(v.clone());
+        }
+
+        // 	appendOffset.x += glyph->advance;
+        self.glyphs.push(entry);
+    }
+
+    // Reserves a grid_width x grid_height rectangle of the grid atlas and
+    // data_len texels of the glyph data buffer on the same page, for a
+    // glyph of `beziers` curves. Pages whose indices are wide enough are
+    // tried in order, then a new one with index_width is opened. Once
+    // there are max_atlas_pages, unused cached glyphs are evicted to make
+    // room, see evict_to_fit. Returns the page, the grid position and the
+    // data offset.
+    fn reserve_atlas_space(
+        &mut self,
+        grid_width: u16,
+        grid_height: u16,
+        data_len: usize,
+        beziers: usize,
+    ) -> Result<(usize, [u16; 2], u32), AtlasError> {
+        // A glyph that doesn't fit on an empty page never will
+        if beziers > self.index_width.max_beziers() {
+            return Err(AtlasError::TooManyBeziers {
+                beziers,
+                max: self.index_width.max_beziers(),
+            });
+        }
+        if data_len > kBezierAtlasSize as usize * kBezierAtlasSize as usize {
+            return Err(AtlasError::GlyphDataTooLarge { texels: data_len });
+        }
+        if grid_width > kGridAtlasSize || grid_height > kGridAtlasSize {
+            return Err(AtlasError::GridTooLarge {
+                width: grid_width,
+                height: grid_height,
+            });
+        }
+
+        loop {
+            for (page, atlas) in self.atlases.iter_mut().enumerate() {
+                if beziers > atlas.index_width.max_beziers() {
+                    continue;
+                }
+                if let Some((grid_pos, data_offset)) =
+                    atlas.reserve(grid_width, grid_height, data_len)
+                {
+                    return Ok((page, grid_pos, data_offset));
+                }
+            }
+
+            if self.atlases.len() < self.max_atlas_pages {
+                self.atlases.push(AtlasGroup::new(self.index_width));
+            } else {
+                self.evict_to_fit(grid_width, grid_height, data_len, beziers)?;
+            }
+        }
+    }
+
+    fn get_glyph_for_codepoint(&mut self, curves: &Vec<Bezier2>) -> Result<CacheEntry, AtlasError> {
+        let glyph_width = 1398;
+        let glyph_height = 1450;
+
+        let hori_bearing_x = 97;
+        let hori_bearing_y = 1430;
+        let hori_advance = 1593;
+
+        let glyph_size = Vec2::from(glyph_width as f32, glyph_height as f32);
+        let (grid_width, grid_height) = choose_grid_size(
+            curves,
+            &glyph_size,
+            self.max_grid_size,
+            kAtlasChannels as usize,
+        );
+
+        let grid = VGrid::from(&curves, &glyph_size, grid_width as u32, grid_height as u32);
+
+        // Although the data is represented as a 32bit texture, it's actually
+        // two 16bit ints per pixel, each with an x and y coordinate for
+        // the bezier. Every six 16bit ints (3 pixels) is a full bezier
+        // Plus two pixels for grid position information
+        let bezierPixelLength = 2 + curves.len() * 3;
+
+        let (page, grid_pos, data_offset) =
+            self.reserve_atlas_space(grid.width, grid.height, bezierPixelLength, curves.len())?;
+        let atlas = &mut self.atlases[page];
+
+        let data_start = data_offset as usize * (kAtlasChannels / 2) as usize;
+        write_glyph_data_to_buffer(
+            &mut atlas.glyph_data_buf[data_start..],
+            &curves,
+            &glyph_size,
+            grid_pos[0],
+            grid_pos[1],
+            grid_width as u16,
+            grid_height as u16,
+        );
+
+        let grid_size = [grid.width, grid.height];
+        if let Err(e) = grid.write_vgrid_at(
+            grid_pos[0],
+            grid_pos[1],
+            &mut atlas.grid_atlas,
+            atlas.index_width,
+        ) {
+            atlas.release(grid_pos, grid_size, (data_offset, bezierPixelLength));
+            return Err(e);
+        }
+        atlas.uploaded = false;
+
+        let mut glyph = Glyph::default();
+        glyph.bezierAtlasPos[0] = data_offset as u16;
+        glyph.bezierAtlasPos[1] = page as u16;
+        glyph.size[0] = glyph_width;
+        glyph.size[1] = glyph_height;
+        glyph.offset[0] = hori_bearing_x;
+        glyph.offset[1] = hori_bearing_y as i16 - glyph_height as i16;
+        glyph.advance = hori_advance;
+
+        Ok(CacheEntry {
+            glyph,
+            key: None,
+            grid_pos,
+            grid_size,
+            data_len: bezierPixelLength,
+            refs: 0,
+            last_used: 0,
+        })
+    }
+
+    // Ranges of verts to draw with each atlas page, for the pages that
+    // have any glyphs, so each page is bound once however its glyphs are
+    // interleaved. Consecutive glyphs are batched into one range.
+    pub fn draw_batches(&self) -> Vec<(usize, Vec<Range<usize>>)> {
+        let mut ranges: Vec<Vec<Range<usize>>> = vec![Vec::new(); self.atlases.len()];
+        for i in 0..self.glyphs.len() {
+            let page = &mut ranges[self.glyph(i).bezierAtlasPos[1] as usize];
+            match page.last_mut() {
+                Some(range) if range.end == i * 6 => range.end = (i + 1) * 6,
+                _ => page.push(i * 6..(i + 1) * 6),
+            }
+        }
+        ranges
+            .into_iter()
+            .enumerate()
+            .filter(|(_, ranges)| !ranges.is_empty())
+            .collect()
+    }
+
+    pub fn atlas_ptr(&self, page: usize) -> *const std::ffi::c_void {
+        self.atlases[page].grid_atlas.as_ptr() as *const std::os::raw::c_void
     }
 
-    pub fn atlas(&self) -> Option<&[u8]> {
-        Some(&self.grid_atlas)
+    pub fn atlas(&self, page: usize) -> Option<&[u8]> {
+        self.atlases.get(page).map(|a| a.grid_atlas.as_slice())
     }
 
     pub fn verts(&self) -> &[u8] {
         unsafe { slice_to_u8(&self.verts) }
     }
 
-    pub fn glyphs(&self) -> &[u8] {
-        unsafe { slice_to_u8(&self.glyph_data_buf) }
+    pub fn glyphs(&self, page: usize) -> Option<&[u8]> {
+        self.atlases
+            .get(page)
+            .map(|a| unsafe { slice_to_u8(&a.glyph_data_buf) })
     }
 
     pub fn verts_ptr(&self) -> *const std::ffi::c_void {
         self.verts.as_ptr() as *const std::os::raw::c_void
     }
 
-    pub fn glgph_ptr(&self) -> *const std::ffi::c_void {
-        self.glyph_data_buf.as_ptr() as *const std::os::raw::c_void
+    pub fn glgph_ptr(&self, page: usize) -> *const std::ffi::c_void {
+        self.atlases[page].glyph_data_buf.as_ptr() as *const std::os::raw::c_void
     }
 }
 
@@ -462,20 +1235,17 @@ pub fn text_framgent_shader() -> String {
 
 impl Default for Grid {
     fn default() -> Self {
-        let mut glyph_data_buf = Vec::new();
-        glyph_data_buf.resize(
-            kBezierAtlasSize as usize * kBezierAtlasSize as usize * (kAtlasChannels / 2) as usize,
-            0,
-        );
-        let mut grid_atlas = Vec::new();
-        grid_atlas.resize(
-            kGridAtlasSize as usize * kGridAtlasSize as usize * kAtlasChannels as usize,
-            0,
-        );
         Grid {
             verts: Vec::new(),
-            glyph_data_buf,
-            grid_atlas,
+            glyphs: Vec::new(),
+            entries: Vec::new(),
+            keys: HashMap::new(),
+            tick: 0,
+            evictions: 0,
+            atlases: Vec::new(),
+            max_grid_size: kGridMaxSize,
+            max_atlas_pages: kMaxAtlasPages,
+            index_width: IndexWidth::Narrow,
             vertex_array_id: 0,
             prog_id: 0,
             glyph_data_buf_id: 0,
@@ -491,44 +1261,40 @@ pub fn create_test_struct() -> Grid {
     let mut grid = Grid::default();
 
     let curves = crate::test_data::test_data::test_curves();
-    insert_curves(
-        &mut grid.verts,
-        &curves,
-        &mut grid.glyph_data_buf,
-        &mut grid.grid_atlas,
-    );
+    grid.insert_curves(&curves, [0.5, 0.0, 0.0, 1.0])
+        .expect("Test curves don't fit in the atlas");
 
     grid
 }
 
 #[cfg(test)]
 mod test {
-    use std::mem::size_of;
+    use std::{collections::HashSet, mem::size_of};
 
     use crate::{
+        atlas::IndexWidth,
         bezier::{Bezier2, Vec2},
-        grid::{find_cells_mids_inside, kBezierAtlasSize, slice_to_u8, vec_as_u8_slice},
-        test_data::test_data::test_curves,
+        grid::{find_cells_mids_inside, slice_to_u8, vec_as_u8_slice},
+        test_data::test_data::{polygon, test_curves},
     };
 
     use super::{
-        find_cells_intersections, insert_curves, kAtlasChannels, kGridAtlasSize, GlVertex, VGrid,
+        choose_grid_size, find_cells_intersections, kAtlasChannels, kBezierAtlasSize,
+        kBezierIndexFirstReal, kGridAtlasLayers, kGridAtlasSize, write_vgrid_cell_to_buffer,
+        AtlasError, CellBeziers, GlVertex, Grid, Relocation, VGrid,
     };
 
     #[test]
     fn test_find_cells_intersections_test_curves() {
         let curves = test_curves();
         let ret = find_cells_intersections(&curves, &Vec2::from(1398.0, 1450.0), 20, 20);
-        let ret: Vec<Vec<usize>> = ret
-            .iter()
-            .map(|v| v.into_iter().cloned().collect())
-            .collect();
+        let ret: Vec<Vec<u32>> = ret.iter().map(|v| v.as_slice().to_vec()).collect();
         let mut sorted = Vec::new();
         for mut v in ret {
             v.sort();
             sorted.extend(v);
         }
-        let exp: Vec<usize> = vec![
+        let exp: Vec<u32> = vec![
             3, 3, 3, 3, 2, 3, 2, 2, 2, 2, 2, 4, 3, 4, 3, 2, 1, 2, 1, 4, 4, 16, 16, 16, 16, 17, 17,
             17, 17, 17, 1, 1, 4, 4, 16, 16, 16, 17, 17, 17, 1, 1, 4, 5, 15, 16, 17, 18, 0, 1, 5, 5,
             15, 15, 18, 18, 0, 0, 5, 15, 18, 0, 5, 15, 15, 18, 18, 0, 5, 15, 18, 0, 5, 15, 18, 0,
@@ -564,23 +1330,407 @@ mod test {
         assert_eq!(ret, exp);
     }
 
+    #[test]
+    fn test_find_cells_mids_inside_winding() {
+        // All on a single row of four unit cells, the midline at y = 0.5.
+        // The square in the last cell is inside, and catches the parity
+        // flips that used to follow a crossing counted wrongly.
+        let glyph_size = Vec2::from(4.0, 1.0);
+        let square = polygon(&[(3.0, 0.0), (4.0, 0.0), (4.0, 1.0), (3.0, 1.0)]);
+        let mids = |mut curves: Vec<Bezier2>| {
+            curves.extend(square.iter().cloned());
+            find_cells_mids_inside(&curves, &glyph_size, 4, 1)
+        };
+
+        // Two squares sharing an edge cross the midline at the same x
+        let left = polygon(&[(0.25, 0.0), (1.0, 0.0), (1.0, 1.0), (0.25, 1.0)]);
+        let right = polygon(&[(1.0, 0.0), (1.75, 0.0), (1.75, 1.0), (1.0, 1.0)]);
+        let shared_edge = left.into_iter().chain(right).collect();
+        assert_eq!(mids(shared_edge), [true, true, false, true]);
+
+        // An arch tangent to the midline from below
+        let mut arch = polygon(&[(0.0, 0.0), (3.0, 0.0)]);
+        arch.truncate(1);
+        arch.push(Bezier2::from(3.0, 0.0, 0.0, 0.0, 1.5, 1.0));
+        assert_eq!(mids(arch), [false, false, false, true]);
+
+        // A triangle whose tip touches the midline from above, where two
+        // of its curves share an endpoint
+        let tip = polygon(&[(1.5, 0.5), (2.0, 1.0), (1.0, 1.0)]);
+        assert_eq!(mids(tip), [false, false, false, true]);
+
+        // A square wound twice is still inside, and one with a hole
+        // wound the other way is not inside the hole
+        let twice = polygon(&[(0.0, 0.0), (3.0, 0.0), (3.0, 1.0), (0.0, 1.0)]);
+        let twice = twice.iter().chain(twice.iter()).cloned().collect();
+        assert_eq!(mids(twice), [true, true, true, true]);
+        let mut hole = polygon(&[(0.0, 0.0), (3.0, 0.0), (3.0, 1.0), (0.0, 1.0)]);
+        hole.extend(polygon(&[
+            (1.0, 0.25),
+            (1.0, 0.75),
+            (2.0, 0.75),
+            (2.0, 0.25),
+        ]));
+        assert_eq!(mids(hole), [true, false, true, true]);
+    }
+
     #[test]
     fn test_find_cells_intersections() {
         let curve = Bezier2::from(1398.0, 731.0, 1313.0, 344.0, 1398.0, 510.0);
         let curves = vec![curve];
         let ret = find_cells_intersections(&curves, &Vec2::from(1398.0, 1450.0), 20, 20);
-        let ret: Vec<usize> = ret.into_iter().flatten().collect();
+        let ret: Vec<u32> = ret.iter().flatten().cloned().collect();
         assert_eq!(ret, vec![0, 0, 0, 0, 0, 0, 0, 0]);
     }
 
+    // Cells of a grid with unit sized cells that the curve is assigned to
+    fn curve_cells(curve: Bezier2, width: u32, height: u32) -> Vec<(u32, u32)> {
+        let glyph_size = Vec2::from(width as f32, height as f32);
+        let cells = find_cells_intersections(&vec![curve], &glyph_size, width, height);
+        (0..width * height)
+            .filter(|i| cells[*i as usize].len() > 0)
+            .map(|i| (i % width, i / width))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_cells_intersections_tricky() {
+        // Starts on a grid line and stays left of it. Sampling grid lines
+        // only put it in the cell of e0, right of the line.
+        let curve = Bezier2::from(1.0, 0.5, 0.75, 0.25, 0.75, 0.75);
+        assert_eq!(curve_cells(curve, 2, 1), [(0, 0), (1, 0)]);
+
+        // Bulges into the next row and back between two columns
+        let curve = Bezier2::from(0.25, 0.75, 0.75, 0.75, 0.5, 1.5);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0), (0, 1)]);
+
+        // Tangent to a grid line from below, touching the row above
+        let curve = Bezier2::from(0.25, 0.5, 0.75, 0.5, 0.5, 1.5);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0), (0, 1)]);
+
+        // Clips the corner of the cell to the right
+        let curve = Bezier2::from(0.5, 0.25, 1.75, 1.5, 1.5, 0.25);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0), (1, 0), (1, 1)]);
+
+        // Passes straight through a corner, touching all four cells
+        let curve = Bezier2::from(0.5, 0.5, 1.5, 1.5, 1.0, 1.0);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        // Lies on a grid line
+        let curve = Bezier2::from(0.5, 1.0, 1.5, 1.0, 1.0, 1.0);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        // Entirely inside one cell
+        let curve = Bezier2::from(0.25, 0.25, 0.75, 0.5, 0.25, 0.75);
+        assert_eq!(curve_cells(curve, 2, 2), [(0, 0)]);
+    }
+
+    // Checks the cells of every curve against dense samples: every
+    // sample inside a cell must be covered, and every covered cell must
+    // be touched by a sample, up to the spacing of the samples
+    fn check_sampled_coverage(curves: &Vec<Bezier2>, glyph_size: &Vec2, width: u32, height: u32) {
+        let cells = find_cells_intersections(curves, glyph_size, width, height);
+        let eps = 0.05;
+        let span = |v: f32, cells: u32| {
+            let first = (v - eps).floor().clamp(0.0, cells as f32 - 1.0) as u32;
+            let last = (v + eps).floor().clamp(0.0, cells as f32 - 1.0) as u32;
+            first..=last
+        };
+        for (i, curve) in curves.iter().enumerate() {
+            let mut touched = HashSet::new();
+            for s in 0..=1024 {
+                let p = curve.point_at(s as f32 / 1024.0);
+                let x = p.x * width as f32 / glyph_size.x;
+                let y = p.y * height as f32 / glyph_size.y;
+                for cy in span(y, height) {
+                    for cx in span(x, width) {
+                        touched.insert(cy * width + cx);
+                    }
+                }
+                let cell = span(y, height).count() == 1 && span(x, width).count() == 1;
+                if cell {
+                    let cell =
+                        (*span(y, height).start() * width + *span(x, width).start()) as usize;
+                    assert!(cells[cell].as_slice().contains(&(i as u32)));
+                }
+            }
+            for (cell, beziers) in cells.iter().enumerate() {
+                if beziers.as_slice().contains(&(i as u32)) {
+                    assert!(touched.contains(&(cell as u32)));
+                }
+            }
+        }
+    }
+
+    // The original HashSet based coverage, which exact coverage only
+    // ever adds cells to
+    fn reference_cells_intersections(
+        beziers: &Vec<Bezier2>,
+        glyph_size: &Vec2,
+        grid_width: u32,
+        grid_height: u32,
+    ) -> Vec<Vec<u32>> {
+        let mut ret = vec![HashSet::new(); (grid_width * grid_height) as usize];
+        let mut setgrid = |x: f32, y: f32, bezier_index: usize| {
+            let x = x.clamp(0.0, grid_width as f32 - 1.0) as u32;
+            let y = y.clamp(0.0, grid_height as f32 - 1.0) as u32;
+            ret[(y * grid_width + x) as usize].insert(bezier_index as u32);
+        };
+        for (i, bezier) in beziers.iter().enumerate() {
+            let mut any_intersections = false;
+            for x in 0..=grid_width {
+                for y in bezier.intersect_vert((x as f32) * glyph_size.x / grid_width as f32) {
+                    let y = y * grid_height as f32 / glyph_size.y;
+                    setgrid(x as f32, y, i);
+                    setgrid(x as f32 - 1.0, y, i);
+                    any_intersections = true;
+                }
+            }
+            for y in 0..=grid_height {
+                for x in bezier.intersect_horz((y as f32) * glyph_size.y / grid_height as f32) {
+                    let x = x * grid_width as f32 / glyph_size.x;
+                    setgrid(x, y as f32, i);
+                    setgrid(x, y as f32 - 1.0, i);
+                    any_intersections = true;
+                }
+            }
+            if !any_intersections {
+                let x = bezier.e0.x * grid_width as f32 / glyph_size.x;
+                let y = bezier.e0.y * grid_height as f32 / glyph_size.y;
+                setgrid(x, y, i);
+            }
+        }
+        ret.into_iter()
+            .map(|cell| {
+                let mut cell: Vec<u32> = cell.into_iter().collect();
+                cell.sort();
+                cell
+            })
+            .collect()
+    }
+
+    // Winding number of the point, on the curves flattened into lines.
+    // Sums the directions of the lines crossing the ray to its right.
+    fn reference_winding(lines: &[(Vec2, Vec2)], p: Vec2) -> i32 {
+        let mut winding = 0;
+        for (a, b) in lines.iter() {
+            let side = (b.x - a.x) * (p.y - a.y) - (p.x - a.x) * (b.y - a.y);
+            if a.y <= p.y && b.y > p.y && side > 0.0 {
+                winding += 1;
+            } else if a.y > p.y && b.y <= p.y && side < 0.0 {
+                winding -= 1;
+            }
+        }
+        winding
+    }
+
+    // Checks the mid of every cell against a brute force winding number,
+    // unless the two disagree about a mid too close to the curves for
+    // the flattening to tell
+    fn check_mids_inside(curves: &Vec<Bezier2>, glyph_size: &Vec2, width: u32, height: u32) {
+        let mids = find_cells_mids_inside(curves, glyph_size, width, height);
+        let scale = |p: Vec2| {
+            Vec2::from(
+                p.x * width as f32 / glyph_size.x,
+                p.y * height as f32 / glyph_size.y,
+            )
+        };
+        let mut lines = Vec::new();
+        for curve in curves.iter() {
+            for s in 0..32 {
+                let a = scale(curve.point_at(s as f32 / 32.0));
+                let b = scale(curve.point_at((s + 1) as f32 / 32.0));
+                lines.push((a, b));
+            }
+        }
+
+        for y in 0..height {
+            let mid_y = y as f32 + 0.5;
+            let row: Vec<(Vec2, Vec2)> = lines
+                .iter()
+                .filter(|(a, b)| a.y.min(b.y) <= mid_y && a.y.max(b.y) >= mid_y)
+                .cloned()
+                .collect();
+            for x in 0..width {
+                let p = Vec2::from(x as f32 + 0.5, mid_y);
+                let inside = reference_winding(&row, p) != 0;
+                if mids[(y * width + x) as usize] != inside {
+                    let near = curves.iter().any(|curve| {
+                        (0..=1024).any(|s| {
+                            let q = scale(curve.point_at(s as f32 / 1024.0));
+                            (q.x - p.x).hypot(q.y - p.y) < 0.05
+                        })
+                    });
+                    assert!(near, "mid of cell ({}, {}) inside: {}", x, y, inside);
+                }
+            }
+        }
+    }
+
+    // A deterministic pseudo random closed contour, with some curves
+    // lying on grid lines
+    fn random_curves(seed: u32, n: usize) -> Vec<Bezier2> {
+        let mut state = seed;
+        let mut next = |max: f32| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 * max
+        };
+        let mut curves = Vec::new();
+        let first = (next(1398.0), next(1450.0));
+        let mut start = first;
+        for i in 0..n {
+            let end = match i % 5 {
+                _ if i == n - 1 => first,
+                0 => (start.0, next(1450.0)),
+                1 => (1398.0 / 4.0, next(1450.0)),
+                _ => (next(1398.0), next(1450.0)),
+            };
+            let c = (next(1398.0), next(1450.0));
+            curves.push(Bezier2::from(start.0, start.1, end.0, end.1, c.0, c.1));
+            start = end;
+        }
+        curves
+    }
+
+    #[test]
+    fn test_vgrid_matches_reference() {
+        let glyph_size = Vec2::from(1398.0, 1450.0);
+        let mut glyphs = vec![test_curves()];
+        for seed in 0..8 {
+            glyphs.push(random_curves(seed, 40));
+        }
+
+        for curves in glyphs.iter() {
+            for (w, h) in [(1, 1), (2, 3), (4, 4), (5, 5), (7, 13), (16, 9), (20, 20)].iter() {
+                let cells = find_cells_intersections(curves, &glyph_size, *w, *h);
+                let reference = reference_cells_intersections(curves, &glyph_size, *w, *h);
+                for (cell, reference) in cells.iter().zip(reference.iter()) {
+                    assert!(reference.iter().all(|i| cell.as_slice().contains(i)));
+                }
+                check_sampled_coverage(curves, &glyph_size, *w, *h);
+                check_mids_inside(curves, &glyph_size, *w, *h);
+            }
+        }
+    }
+
+    // Collects a glyph outline as beziers, lines having their control
+    // point halfway
+    #[derive(Default)]
+    struct Outline {
+        curves: Vec<Bezier2>,
+        start: Vec2,
+        last: Vec2,
+    }
+
+    impl ttf_parser::OutlineBuilder for Outline {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.start = Vec2::from(x, y);
+            self.last = self.start;
+        }
+
+        fn line_to(&mut self, x: f32, y: f32) {
+            let (a, c) = (
+                self.last,
+                Vec2::from((self.last.x + x) / 2.0, (self.last.y + y) / 2.0),
+            );
+            self.curves.push(Bezier2::from(a.x, a.y, x, y, c.x, c.y));
+            self.last = Vec2::from(x, y);
+        }
+
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            let a = self.last;
+            self.curves.push(Bezier2::from(a.x, a.y, x, y, x1, y1));
+            self.last = Vec2::from(x, y);
+        }
+
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {
+            panic!("TrueType outlines only have quadratic curves");
+        }
+
+        fn close(&mut self) {
+            if (self.last.x, self.last.y) != (self.start.x, self.start.y) {
+                self.line_to(self.start.x, self.start.y);
+            }
+        }
+    }
+
+    // Every non-empty glyph of the font, moved to the origin, with its size
+    fn font_glyphs(data: &[u8]) -> Vec<(Vec<Bezier2>, Vec2)> {
+        let face = ttf_parser::Face::parse(data, 0).unwrap();
+        let mut glyphs = Vec::new();
+        for id in 0..face.number_of_glyphs() {
+            let mut outline = Outline::default();
+            let rect = match face.outline_glyph(ttf_parser::GlyphId(id), &mut outline) {
+                Some(rect) if rect.width() > 0 && rect.height() > 0 => rect,
+                _ => continue,
+            };
+            let (x0, y0) = (rect.x_min as f32, rect.y_min as f32);
+            let curves = outline
+                .curves
+                .iter()
+                .map(|b| {
+                    Bezier2::from(
+                        b.e0.x - x0,
+                        b.e0.y - y0,
+                        b.e1.x - x0,
+                        b.e1.y - y0,
+                        b.c.x - x0,
+                        b.c.y - y0,
+                    )
+                })
+                .collect();
+            let size = Vec2::from(rect.width() as f32, rect.height() as f32);
+            glyphs.push((curves, size));
+        }
+        glyphs
+    }
+
+    #[test]
+    fn test_mids_inside_font_glyphs() {
+        let fonts: [&[u8]; 2] = [
+            include_bytes!("../fonts/LiberationSans-Regular.ttf"),
+            include_bytes!("../fonts/LiberationSans-Bold.ttf"),
+        ];
+        for font in fonts.iter() {
+            let glyphs = font_glyphs(font);
+            assert!(glyphs.len() > 1000);
+            for (curves, size) in glyphs.iter() {
+                for (w, h) in [(7, 13), (20, 20)].iter() {
+                    check_mids_inside(curves, size, *w, *h);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cell_beziers() {
+        let mut cell = CellBeziers::default();
+        for i in [5, 1, 3, 1, 9, 0, 2, 8, 7].iter() {
+            cell.insert(*i);
+        }
+        assert_eq!(cell.as_slice(), [0, 1, 2, 3, 5, 7, 8, 9]);
+        assert!(cell.spilled.is_empty());
+
+        // Spills to the heap once full, staying sorted
+        cell.insert(4);
+        cell.insert(6);
+        cell.insert(4);
+        assert_eq!(cell.as_slice(), [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(cell.len(), 10);
+    }
+
     #[test]
     fn test_write_vgrid_at() {
         let curves = test_curves();
         let grid = VGrid::from(&curves, &Vec2::from(1398.0, 1450.0), 20, 20);
-        let n = kGridAtlasSize as usize * kGridAtlasSize as usize * kAtlasChannels as usize;
+        let n = kGridAtlasSize as usize
+            * kGridAtlasSize as usize
+            * kAtlasChannels as usize
+            * kGridAtlasLayers as usize;
         let mut data = Vec::new();
         data.resize(n as usize, 0);
-        grid.write_vgrid_at(0, 0, &mut data);
+        grid.write_vgrid_at(0, 0, &mut data, IndexWidth::Narrow)
+            .unwrap();
         let exp: [u8; 256] = [
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 5, 0, 0, 0, 5,
             0, 0, 0, 5, 0, 0, 0, 5, 4, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 0, 4,
@@ -596,21 +1746,426 @@ mod test {
     }
 
     #[test]
-    fn test_insert_curves() {
-        let mut glyph_data_buf = Vec::new();
-        glyph_data_buf.resize(
-            kBezierAtlasSize as usize * kBezierAtlasSize as usize * (kAtlasChannels / 2) as usize,
-            0,
+    fn test_write_vgrid_cell_to_buffer_overflow() {
+        let cell_beziers = vec![(0..6).collect(), (0..8).collect(), (0..4).collect()];
+        let cell_mids = vec![true, false, false];
+        let mut data = [0_u16; 8];
+
+        // Indices past the first texel are flagged by data[2] > data[3]
+        write_vgrid_cell_to_buffer(&cell_beziers, &cell_mids, 0, &mut data, 4);
+        assert_eq!(data, [3, 2, 5, 4, 6, 7, 0, 0]);
+        write_vgrid_cell_to_buffer(&cell_beziers, &cell_mids, 1, &mut data, 4);
+        assert_eq!(data, [2, 3, 5, 4, 6, 7, 8, 9]);
+        write_vgrid_cell_to_buffer(&cell_beziers, &cell_mids, 2, &mut data, 4);
+        assert_eq!(data, [2, 3, 4, 5, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_write_vgrid_at_overflow() {
+        // Six curves inside a single cell
+        let curves: Vec<Bezier2> = (0..6)
+            .map(|i| {
+                let y = 10.0 + i as f32 * 10.0;
+                Bezier2::from(10.0, y, 90.0, y, 50.0, y + 5.0)
+            })
+            .collect();
+        let glyph_size = Vec2::from(100.0, 100.0);
+        let mut data = vec![0_u8; 2 * 2 * 4 * 2];
+
+        let grid = VGrid::from(&curves, &glyph_size, 1, 1);
+        grid._write_vgrid_at(1, 1, &mut data, 2, 2, 4, 2, IndexWidth::Narrow)
+            .unwrap();
+        assert_eq!(data[12..16], [3, 2, 5, 4]);
+        assert_eq!(data[28..32], [6, 7, 0, 0]);
+
+        let mut curves = curves;
+        curves.extend(curves.clone());
+        curves.truncate(9);
+        let grid = VGrid::from(&curves, &glyph_size, 1, 1);
+        assert_eq!(
+            grid._write_vgrid_at(0, 0, &mut data, 2, 2, 4, 2, IndexWidth::Narrow),
+            Err(AtlasError::TooManyBeziersInCell {
+                cell: 0,
+                beziers: 9
+            })
+        );
+    }
+
+    #[test]
+    fn test_choose_grid_size() {
+        // A thin stem fits in one column
+        let stem = vec![
+            Bezier2::from(0.0, 0.0, 100.0, 0.0, 50.0, 0.0),
+            Bezier2::from(100.0, 0.0, 100.0, 1000.0, 100.0, 500.0),
+            Bezier2::from(100.0, 1000.0, 0.0, 1000.0, 50.0, 1000.0),
+            Bezier2::from(0.0, 1000.0, 0.0, 0.0, 0.0, 500.0),
+        ];
+        assert_eq!(
+            choose_grid_size(&stem, &Vec2::from(100.0, 1000.0), 20, 4),
+            (1, 1)
+        );
+        // Both sides run through every cell of a single column, so
+        // allowing only two beziers per cell takes a second column
+        assert_eq!(
+            choose_grid_size(&stem, &Vec2::from(100.0, 1000.0), 20, 2),
+            (2, 15)
+        );
+
+        let curves = test_curves();
+        let glyph_size = Vec2::from(1398.0, 1450.0);
+        assert_eq!(choose_grid_size(&curves, &glyph_size, 20, 4), (5, 5));
+        assert_eq!(choose_grid_size(&curves, &glyph_size, 4, 4), (4, 4));
+        assert_eq!(choose_grid_size(&curves, &glyph_size, 20, 2), (10, 10));
+    }
+
+    // The test glyph, a color to draw it with and an empty grid
+    fn test_glyph_grid() -> (Vec<Bezier2>, [f32; 4], Grid) {
+        (test_curves(), [0.5, 0.0, 0.0, 1.0], Grid::default())
+    }
+
+    // Fills what's left of a page's grid atlas, so new glyphs go elsewhere
+    fn fill_page(grid: &mut Grid, page: usize) {
+        let packer = &mut grid.atlases[page].grid_packer;
+        for width in [kGridAtlasSize, 16, 1] {
+            while packer.insert(width, 1).is_some() {}
+        }
+    }
+
+    // A short line in the middle of each of the first `count` cells of a
+    // columns x rows grid over the glyph
+    fn short_lines(count: usize, columns: usize, rows: usize) -> Vec<Bezier2> {
+        let (cell_w, cell_h) = (1398.0 / columns as f32, 1450.0 / rows as f32);
+        (0..count)
+            .map(|i| {
+                let x = (i % columns) as f32 * cell_w + cell_w / 4.0;
+                let y = (i / columns) as f32 * cell_h + cell_h / 2.0;
+                Bezier2::from(x, y, x + cell_w / 2.0, y, x + cell_w / 4.0, y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_curves_pages() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        grid.insert_curves(&curves, color).unwrap();
+        grid.insert_curves(&curves, color).unwrap();
+
+        // The second 5x5 grid is packed right of the first
+        let atlas = &grid.atlases[0].grid_atlas;
+        let row = kGridAtlasSize as usize * kAtlasChannels as usize;
+        for y in 0..5 {
+            assert_eq!(
+                atlas[y * row..y * row + 20],
+                atlas[y * row + 20..y * row + 40]
+            );
+        }
+
+        // Once the first page is full, glyphs go to a new one
+        fill_page(&mut grid, 0);
+        grid.insert_curves(&curves, color).unwrap();
+        grid.insert_curves(&curves, color).unwrap();
+
+        assert_eq!(grid.atlases.len(), 2);
+        assert_eq!(grid.glyph(2).bezierAtlasPos[1], 1);
+        assert_eq!(
+            grid.draw_batches(),
+            vec![(0, vec![0..12]), (1, vec![12..24])]
+        );
+
+        // Glyphs alternating pages still draw each page once
+        grid.remove_glyphs(0..1);
+        grid.insert_curves(&curves, color).unwrap();
+        assert_eq!(
+            grid.draw_batches(),
+            vec![(0, vec![0..6, 18..24]), (1, vec![6..18])]
+        );
+    }
+
+    #[test]
+    fn test_insert_curves_data_offsets() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        let len = 2 + curves.len() as u16 * 3;
+        let per_row = kBezierAtlasSize / len;
+        for _ in 0..per_row + 1 {
+            grid.insert_curves(&curves, color).unwrap();
+        }
+
+        // Each glyph's data follows the previous one's, with no gap where
+        // a row of the original 2D texture would end
+        for i in 0..per_row + 1 {
+            assert_eq!(grid.glyph(i as usize).bezierAtlasPos[0], i * len);
+        }
+
+        // The vertices point at the glyph's data
+        for (j, v) in grid.verts[6..12].iter().enumerate() {
+            assert_eq!(v.data >> 2, len as u32, "vertex {}", j);
+        }
+
+        // Header with the second glyph's grid position, then its curves
+        let buf = &grid.atlases[0].glyph_data_buf;
+        let start = len as usize * 2;
+        assert_eq!(buf[start..start + 4], [5, 0, 5, 5]);
+        assert_eq!(buf[start + 4..start + 10], buf[4..10]);
+    }
+
+    #[test]
+    fn test_glyph_data_full() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        grid.insert_curves(&curves, color).unwrap();
+
+        // Out of glyph data space moves on to a new page
+        let alloc = &mut grid.atlases[0].glyph_data_alloc;
+        for len in [kBezierAtlasSize as usize, 1] {
+            while alloc.alloc(len).is_ok() {}
+        }
+        grid.insert_curves(&curves, color).unwrap();
+        assert_eq!(grid.atlases.len(), 2);
+        assert_eq!(grid.glyph(1).bezierAtlasPos, [0, 1]);
+    }
+
+    #[test]
+    fn test_insert_curves_narrow_max_beziers() {
+        // More data than a 256 texel row but few enough beziers for Narrow
+        let (small, color, mut grid) = test_glyph_grid();
+        grid.insert_curves(&small, color).unwrap();
+        grid.insert_curves(&short_lines(100, 20, 5), color).unwrap();
+        assert_eq!(grid.atlases.len(), 1);
+        assert_eq!(grid.atlases[0].index_width, IndexWidth::Narrow);
+        let offset = 2 + small.len() as u16 * 3;
+        assert_eq!(grid.glyph(1).bezierAtlasPos, [offset, 0]);
+        assert_eq!(grid.stats().glyph_data_used, offset as usize + 2 + 100 * 3);
+    }
+
+    #[test]
+    fn test_glyph_cache() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        grid.max_atlas_pages = 1;
+
+        // Quads drawing the same key share its atlas space
+        grid.insert_glyph(1, &curves, color).unwrap();
+        grid.insert_glyph(1, &curves, color).unwrap();
+        assert_eq!({ grid.verts[0].data }, { grid.verts[6].data });
+        let stats = grid.stats();
+        assert_eq!((stats.cached_glyphs, stats.referenced_glyphs), (1, 1));
+        assert_eq!(stats.grid_cells_used, 25);
+        assert_eq!(stats.glyph_data_used, 2 + curves.len() * 3);
+
+        // Uncached glyphs are freed along with their quad
+        grid.insert_curves(&curves, color).unwrap();
+        grid.remove_glyphs(2..3);
+        assert_eq!(grid.stats().grid_cells_used, 25);
+
+        // Unreferenced glyphs stay cached
+        grid.remove_glyphs(0..2);
+        let stats = grid.stats();
+        assert_eq!((stats.cached_glyphs, stats.referenced_glyphs), (1, 0));
+        assert_eq!(stats.grid_cells_used, 25);
+        grid.insert_glyph(1, &curves, color).unwrap();
+        grid.insert_glyph(2, &curves, color).unwrap();
+        grid.remove_glyphs(0..2);
+
+        // Once the page is full, the least recently used glyph is evicted
+        // and its space reused
+        fill_page(&mut grid, 0);
+        grid.insert_glyph(2, &curves, color).unwrap();
+        grid.insert_glyph(3, &curves, color).unwrap();
+        assert_eq!(grid.atlases.len(), 1);
+        assert_eq!(grid.stats().evictions, 1);
+        assert!(!grid.keys.contains_key(&1));
+        assert_eq!(grid.glyph(1).bezierAtlasPos, [0, 0]);
+
+        // Nothing left to evict
+        assert_eq!(
+            grid.insert_glyph(4, &curves, color),
+            Err(AtlasError::AtlasFull { pages: 1 })
+        );
+        assert_eq!(grid.stats().cached_glyphs, 2);
+    }
+
+    #[test]
+    fn test_glyph_cache_evict_to_fit() {
+        let square = polygon(&[(0.0, 0.0), (1398.0, 0.0), (1398.0, 1450.0), (0.0, 1450.0)]);
+        let large = short_lines(84, 12, 7);
+        let (_, color, mut grid) = test_glyph_grid();
+        grid.max_atlas_pages = 1;
+
+        // Fill the page with small glyphs that are all drawn
+        let mut filled = 0;
+        while grid.insert_glyph(filled, &square, color).is_ok() {
+            filled += 1;
+        }
+        assert_eq!(grid.stats().evictions, 0);
+
+        // Too few unused glyphs to make room, so none are evicted
+        grid.remove_glyphs(0..2);
+        assert_eq!(
+            grid.insert_curves(&large, color),
+            Err(AtlasError::AtlasFull { pages: 1 })
+        );
+        let stats = grid.stats();
+        assert_eq!((stats.cached_glyphs, stats.evictions), (filled as usize, 0));
+
+        // The space of the evicted glyphs merges back together, so some
+        // of them make room and the rest stay cached
+        grid.remove_glyphs(0..filled as usize - 2);
+        grid.insert_curves(&large, color).unwrap();
+        let stats = grid.stats();
+        assert!(stats.evictions > 0);
+        assert_eq!(stats.cached_glyphs + stats.evictions, filled as usize);
+        assert_eq!(stats.referenced_glyphs, 0);
+    }
+
+    #[test]
+    fn test_compact() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        let len = 2 + curves.len() as u16 * 3;
+        for _ in 0..3 {
+            grid.insert_curves(&curves, color).unwrap();
+        }
+        grid.remove_glyphs(0..1);
+        let stats = grid.stats();
+
+        let relocations = grid.compact().unwrap();
+        assert_eq!(
+            relocations,
+            [
+                Relocation {
+                    old_page: 0,
+                    old_offset: len,
+                    new_page: 0,
+                    new_offset: 0
+                },
+                Relocation {
+                    old_page: 0,
+                    old_offset: len * 2,
+                    new_page: 0,
+                    new_offset: len
+                }
+            ]
+        );
+        assert_eq!(grid.stats(), stats);
+        assert!(!grid.atlases[0].uploaded);
+
+        // Verts, grids and glyph data all match a grid that never had the
+        // removed glyph
+        let mut fresh = Grid::default();
+        for _ in 0..2 {
+            fresh.insert_curves(&curves, color).unwrap();
+        }
+        assert_eq!(grid.verts(), fresh.verts());
+        assert_eq!(grid.atlases[0].grid_atlas, fresh.atlases[0].grid_atlas);
+        assert_eq!(
+            grid.atlases[0].glyph_data_buf[..len as usize * 4],
+            fresh.atlases[0].glyph_data_buf[..len as usize * 4]
         );
 
-        let mut grid_atlas = Vec::new();
-        grid_atlas.resize(
-            kGridAtlasSize as usize * kGridAtlasSize as usize * kAtlasChannels as usize,
-            0,
+        // The freed space is in one piece again
+        let packer = &mut grid.atlases[0].grid_packer;
+        assert_eq!(packer.insert(kGridAtlasSize - 10, 5), Some([10, 0]));
+    }
+
+    #[test]
+    fn test_compact_page_limit() {
+        let (curves, color, mut grid) = test_glyph_grid();
+        grid.insert_curves(&curves, color).unwrap();
+        fill_page(&mut grid, 0);
+        grid.insert_curves(&curves, color).unwrap();
+        assert_eq!(grid.atlases.len(), 2);
+
+        // Nothing changes when the glyphs don't fit the pages allowed
+        let verts = grid.verts().to_vec();
+        grid.max_atlas_pages = 0;
+        assert_eq!(grid.compact(), Err(AtlasError::AtlasFull { pages: 0 }));
+        assert_eq!(grid.atlases.len(), 2);
+        assert_eq!(grid.glyph(1).bezierAtlasPos, [0, 1]);
+        assert_eq!(grid.verts(), verts);
+
+        // Both glyphs fit on the one page allowed once repacked
+        grid.max_atlas_pages = 1;
+        assert_eq!(grid.compact().unwrap().len(), 1);
+        assert_eq!(grid.atlases.len(), 1);
+    }
+
+    #[test]
+    fn test_write_vgrid_at_wide() {
+        let grid = VGrid {
+            width: 1,
+            height: 1,
+            cellBeziers: vec![vec![300, 2].into_iter().collect()],
+            cellMids: vec![false],
+        };
+
+        let mut data = vec![0_u8; 4 * 2];
+        assert_eq!(
+            grid._write_vgrid_at(0, 0, &mut data, 1, 1, 4, 2, IndexWidth::Narrow),
+            Err(AtlasError::TooManyBeziers {
+                beziers: 301,
+                max: 254
+            })
         );
+        assert!(data.iter().all(|b| *b == 0));
+
+        let mut data = vec![0_u8; 4 * 2 * 2];
+        grid._write_vgrid_at(0, 0, &mut data, 1, 1, 4, 2, IndexWidth::Wide)
+            .unwrap();
+        let indices: Vec<u16> = data
+            .chunks(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(indices, [4, 302, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_insert_curves_wide() {
+        // Lines in 15 rows of a 20x20 grid
+        let curves = short_lines(300, 20, 20);
+        let (small, color, mut grid) = test_glyph_grid();
+        assert_eq!(
+            grid.insert_curves(&curves, color),
+            Err(AtlasError::TooManyBeziers {
+                beziers: 300,
+                max: 254
+            })
+        );
+        assert!(grid.atlases.is_empty());
+
+        // Glyphs that don't need wide indices can still use wide pages
+        grid.index_width = IndexWidth::Wide;
+        grid.insert_curves(&curves, color).unwrap();
+        grid.insert_curves(&small, color).unwrap();
+        assert_eq!(grid.atlases.len(), 1);
+        assert_eq!(grid.atlases[0].index_width, IndexWidth::Wide);
+        assert_eq!(grid.glyph(1).bezierAtlasPos, [2 + 300 * 3, 0]);
+
+        // Every bezier is referenced by its full index
+        let [width, height] = grid.entries[grid.glyphs[0]].as_ref().unwrap().grid_size;
+        let atlas = &grid.atlases[0].grid_atlas;
+        let layer_size = kGridAtlasSize as usize * kGridAtlasSize as usize * 4;
+        let mut found = HashSet::new();
+        for layer in 0..kGridAtlasLayers as usize {
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    for channel in 0..4 {
+                        let i =
+                            (layer * layer_size + (y * kGridAtlasSize as usize + x) * 4 + channel)
+                                * 2;
+                        found.insert(u16::from_ne_bytes([atlas[i], atlas[i + 1]]));
+                    }
+                }
+            }
+        }
+        found.retain(|i| *i >= kBezierIndexFirstReal);
+        assert_eq!(found, (2..302).collect());
+    }
+
+    #[test]
+    fn test_insert_curves() {
         let curves = test_curves();
-        let mut verts = Vec::new();
-        insert_curves(&mut verts, &curves, &mut glyph_data_buf, &mut grid_atlas);
+        let mut grid = Grid::default();
+        grid.insert_curves(&curves, [0.5, 0.0, 0.0, 1.0]).unwrap();
+        let verts = grid.verts.clone();
+        let grid_atlas = grid.atlases[0].grid_atlas.clone();
+        let glyph_data_buf = grid.atlases[0].glyph_data_buf.clone();
 
         //     let ptr = verts.as_ptr() as u8;
         //     assert_eq!(ptr,)
@@ -661,21 +2216,20 @@ mod test {
                 ]
             );
         }
+        // The test glyph gets a 5x5 grid at the atlas origin
+        let row = kGridAtlasSize as usize * kAtlasChannels as usize;
+        let rows: Vec<&[u8]> = (0..5).map(|y| &grid_atlas[y * row..y * row + 20]).collect();
         assert_eq!(
-            grid_atlas[0..256],
+            rows,
             [
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 5, 0, 0, 0,
-                5, 0, 0, 0, 5, 0, 0, 0, 5, 4, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0,
-                0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0,
+                [0, 6, 0, 0, 6, 5, 18, 0, 5, 4, 18, 19, 4, 3, 19, 0, 0, 3, 0, 0],
+                [7, 6, 17, 0, 17, 18, 0, 0, 0, 0, 0, 0, 19, 20, 0, 0, 3, 2, 20, 0],
+                [8, 7, 16, 17, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 12, 2, 13, 20],
+                [16, 8, 0, 0, 15, 16, 0, 0, 0, 0, 0, 0, 13, 14, 0, 0, 12, 11, 13, 0],
+                [8, 9, 0, 0, 15, 9, 0, 0, 10, 9, 14, 15, 11, 10, 14, 0, 0, 11, 0, 0],
             ]
         );
+        assert!(grid_atlas[20..row].iter().all(|b| *b == 0));
         let (ptr, size) = ffi_utils::vec_into_raw_parts(glyph_data_buf);
         let vec: Vec<u8> = unsafe {
             let size = size * size_of::<u8>() as usize;
@@ -684,7 +2238,7 @@ mod test {
         assert_eq!(
             vec[0..256],
             [
-                0, 0, 0, 0, 20, 0, 20, 0, 255, 255, 14, 129, 255, 255, 10, 90, 110, 240, 187, 60,
+                0, 0, 0, 0, 5, 0, 5, 0, 255, 255, 14, 129, 255, 255, 10, 90, 110, 240, 187, 60,
                 110, 240, 187, 60, 12, 225, 108, 31, 29, 196, 182, 15, 29, 196, 182, 15, 47, 167,
                 0, 0, 208, 127, 0, 0, 208, 127, 0, 0, 20, 88, 0, 0, 37, 59, 137, 15, 37, 59, 137,
                 15, 101, 30, 18, 31, 50, 15, 97, 60, 50, 15, 97, 60, 0, 0, 221, 89, 0, 0, 14, 129,
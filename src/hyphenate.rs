@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+// Hyphenates words using Liang's algorithm with TeX-style patterns, as
+// found in the hyph-*.pat.txt files shipped with TeX distributions.
+// Patterns are plain text, whitespace separated, e.g. "hy3ph he2n 1na".
+// Odd digits between letters allow a break, even digits forbid it and
+// the highest digit from all matching patterns wins.
+pub struct Hyphenator {
+    patterns: HashMap<String, Vec<u8>>,
+    exceptions: HashMap<String, Vec<usize>>,
+    max_pattern_len: usize,
+
+    // Minimum number of characters before the first and after the
+    // last break (TeX's \lefthyphenmin and \righthyphenmin)
+    pub left_min: usize,
+    pub right_min: usize,
+}
+
+impl Hyphenator {
+    pub fn from_patterns(patterns: &str) -> Hyphenator {
+        let mut hyphenator = Hyphenator {
+            patterns: HashMap::new(),
+            exceptions: HashMap::new(),
+            max_pattern_len: 0,
+            left_min: 2,
+            right_min: 3,
+        };
+
+        for pattern in words(patterns) {
+            let mut letters = String::new();
+            let mut values = vec![0_u8];
+            for c in pattern.chars() {
+                match c.to_digit(10) {
+                    Some(d) => *values.last_mut().unwrap() = d as u8,
+                    None => {
+                        letters.extend(c.to_lowercase());
+                        values.push(0);
+                    }
+                }
+            }
+            hyphenator.max_pattern_len = hyphenator.max_pattern_len.max(values.len() - 1);
+            hyphenator.patterns.insert(letters, values);
+        }
+
+        hyphenator
+    }
+
+    // Exceptions override the patterns for whole words and are written
+    // with explicit hyphens, e.g. "as-so-ciate ta-ble".
+    pub fn add_exceptions(&mut self, exceptions: &str) {
+        for exception in words(exceptions) {
+            let mut word = String::new();
+            let mut breaks = Vec::new();
+            for c in exception.chars() {
+                if c == '-' {
+                    breaks.push(word.chars().count());
+                } else {
+                    word.extend(c.to_lowercase());
+                }
+            }
+            self.exceptions.insert(word, breaks);
+        }
+    }
+
+    // Returns the byte offsets into `word` before which a hyphen may be
+    // inserted. If the word contains soft hyphens (U+00AD), only those
+    // are used as break points.
+    pub fn hyphenate(&self, word: &str) -> Vec<usize> {
+        if word.contains(SOFT_HYPHEN) {
+            return word
+                .char_indices()
+                .filter(|(_, c)| *c == SOFT_HYPHEN)
+                .map(|(i, c)| i + c.len_utf8())
+                .filter(|i| *i < word.len())
+                .collect();
+        }
+
+        let offsets: Vec<usize> = word.char_indices().map(|(i, _)| i).collect();
+        let n = offsets.len();
+        if n < self.left_min + self.right_min {
+            return Vec::new();
+        }
+
+        // Character indices a break may go before. There's always at
+        // least one character on either side, whatever the minimums.
+        let first = self.left_min.max(1);
+        let last = n.saturating_sub(self.right_min.max(1));
+
+        let lower: String = word.chars().flat_map(char::to_lowercase).collect();
+        if let Some(breaks) = self.exceptions.get(&lower) {
+            return breaks
+                .iter()
+                .filter(|i| **i >= first && **i <= last)
+                .map(|i| offsets[*i])
+                .collect();
+        }
+
+        // Lowercasing can change the char count (e.g. 'İ'), in which case
+        // the values can't be mapped back onto the word
+        let letters: Vec<char> = format!(".{}.", lower).chars().collect();
+        if letters.len() != n + 2 {
+            return Vec::new();
+        }
+
+        // values[i] is the priority of a break before letters[i]
+        let mut values = vec![0_u8; letters.len() + 1];
+        for start in 0..letters.len() {
+            let mut key = String::new();
+            for c in letters[start..].iter().take(self.max_pattern_len) {
+                key.push(*c);
+                if let Some(pattern) = self.patterns.get(&key) {
+                    for (i, v) in pattern.iter().enumerate() {
+                        values[start + i] = values[start + i].max(*v);
+                    }
+                }
+            }
+        }
+
+        // Break before word character i, which is letters[i + 1]
+        (first..=last)
+            .filter(|i| values[i + 1] % 2 == 1)
+            .map(|i| offsets[i])
+            .collect()
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.split('%').next().unwrap())
+        .flat_map(str::split_whitespace)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Hyphenator;
+
+    // Patterns matching "hyphenation" from Liang's thesis
+    const PATTERNS: &str = "
+        % comment lines are ignored
+        hy3ph he2n hena4 hen5at 1na n2at
+        1tio 2io o2n
+    ";
+
+    fn split(word: &str, breaks: &[usize]) -> Vec<String> {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        for b in breaks {
+            parts.push(word[start..*b].to_string());
+            start = *b;
+        }
+        parts.push(word[start..].to_string());
+        parts
+    }
+
+    #[test]
+    fn test_hyphenate() {
+        let h = Hyphenator::from_patterns(PATTERNS);
+        let word = "Hyphenation";
+        assert_eq!(split(word, &h.hyphenate(word)), ["Hy", "phen", "ation"]);
+        assert_eq!(h.hyphenate("hy"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_hyphenate_min_lengths() {
+        let mut h = Hyphenator::from_patterns(PATTERNS);
+        h.left_min = 3;
+        let word = "hyphenation";
+        assert_eq!(split(word, &h.hyphenate(word)), ["hyphen", "ation"]);
+    }
+
+    #[test]
+    fn test_hyphenate_exceptions() {
+        let mut h = Hyphenator::from_patterns(PATTERNS);
+        h.add_exceptions("hy-phe-na-tion");
+        let word = "hyphenation";
+        assert_eq!(split(word, &h.hyphenate(word)), ["hy", "phe", "na", "tion"]);
+
+        // Exceptions are held to the minimum lengths too
+        h.add_exceptions("a-bcde");
+        assert_eq!(h.hyphenate("abcde"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_hyphenate_zero_min_lengths() {
+        let mut h = Hyphenator::from_patterns("n1.");
+        h.left_min = 1;
+        h.right_min = 0;
+        assert_eq!(h.hyphenate("ban"), Vec::<usize>::new());
+        h.left_min = 0;
+        assert_eq!(h.hyphenate("ban"), Vec::<usize>::new());
+
+        let mut h = Hyphenator::from_patterns("1b");
+        h.left_min = 0;
+        h.right_min = 0;
+        assert_eq!(h.hyphenate("bab"), vec![2]);
+        assert_eq!(h.hyphenate(""), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_hyphenate_soft_hyphens() {
+        let h = Hyphenator::from_patterns(PATTERNS);
+        let word = "hyphen\u{ad}ation\u{ad}";
+        assert_eq!(h.hyphenate(word), vec![8]);
+    }
+}
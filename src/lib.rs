@@ -1,11 +1,16 @@
 use std::slice;
 
-use bezier::{Bezier2, Vec2};
+pub use bezier::{Bezier2, Vec2};
 
 mod test_data;
 mod bezier;
 mod buffer;
+pub mod animate;
+pub mod atlas;
 pub mod grid;
+pub mod hyphenate;
+pub mod markup;
+pub mod path;
 
 // src/lib.rs
 // #![feature(vec_into_raw_parts)]
@@ -0,0 +1,258 @@
+use std::{fmt, ops::Range};
+
+// Style of a run of text. Unset fields (None) fall back to the label's
+// defaults when generating glyph quads.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub color: Option<[f32; 4]>, // RGBA [0,1], as taken by insert_curves
+    pub size: Option<f32>,
+    pub font: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkupError {
+    pub message: String,
+    pub start: usize, // Byte offsets of the offending markup
+    pub end: usize,
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at bytes {}..{}", self.message, self.start, self.end)
+    }
+}
+
+impl std::error::Error for MarkupError {}
+
+fn error(message: String, start: usize, end: usize) -> MarkupError {
+    MarkupError {
+        message,
+        start,
+        end,
+    }
+}
+
+// Parses a small markup language into styled spans:
+//   <b>bold</b> <i>italic</i> <u>underline</u>
+//   <color=#rrggbb>..</color> (or #rrggbbaa)
+//   <size=24>..</size> <font=Liberation Sans>..</font>
+// Tags must be closed in the reverse order they were opened. A literal
+// '<', '>' or '&' is written as &lt; &gt; or &amp;. Adjacent spans with
+// the same style are merged.
+pub fn parse(markup: &str) -> Result<Vec<Span>, MarkupError> {
+    // Open tags as (name, byte range of the tag, style before the tag)
+    let mut open: Vec<(&str, Range<usize>, Style)> = Vec::new();
+    let mut style = Style::default();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut text = String::new();
+
+    let mut i = 0;
+    while i < markup.len() {
+        let rest = &markup[i..];
+        if rest.starts_with('&') {
+            let entity = [("&lt;", '<'), ("&gt;", '>'), ("&amp;", '&')]
+                .iter()
+                .find(|(e, _)| rest.starts_with(e));
+            match entity {
+                Some((e, c)) => {
+                    text.push(*c);
+                    i += e.len();
+                }
+                None => {
+                    text.push('&');
+                    i += 1;
+                }
+            }
+            continue;
+        } else if rest.starts_with('>') {
+            return Err(error("unexpected '>'".to_string(), i, i + 1));
+        } else if !rest.starts_with('<') {
+            let c = rest.chars().next().unwrap();
+            text.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+
+        let tag_end = match rest.find('>') {
+            Some(len) => i + len + 1,
+            None => return Err(error("unterminated tag".to_string(), i, markup.len())),
+        };
+        let tag = &markup[i + 1..tag_end - 1];
+
+        push_span(&mut spans, &mut text, &style);
+
+        if let Some(name) = tag.strip_prefix('/') {
+            match open.pop() {
+                Some((open_name, _, prev)) if open_name == name => style = prev,
+                Some((open_name, _, _)) => {
+                    return Err(error(
+                        format!("expected </{}>, found </{}>", open_name, name),
+                        i,
+                        tag_end,
+                    ))
+                }
+                None => {
+                    return Err(error(
+                        format!("closing tag </{}> was never opened", name),
+                        i,
+                        tag_end,
+                    ))
+                }
+            }
+        } else {
+            let (name, value) = match tag.find('=') {
+                Some(eq) => (&tag[..eq], Some(&tag[eq + 1..])),
+                None => (tag, None),
+            };
+            open.push((name, i..tag_end, style.clone()));
+            apply_tag(&mut style, name, value).map_err(|message| error(message, i, tag_end))?;
+        }
+
+        i = tag_end;
+    }
+
+    if let Some((name, tag, _)) = open.pop() {
+        return Err(error(
+            format!("<{}> is never closed", name),
+            tag.start,
+            tag.end,
+        ));
+    }
+
+    push_span(&mut spans, &mut text, &style);
+    Ok(spans)
+}
+
+fn push_span(spans: &mut Vec<Span>, text: &mut String, style: &Style) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == *style => last.text.push_str(text),
+        _ => spans.push(Span {
+            text: text.clone(),
+            style: style.clone(),
+        }),
+    }
+    text.clear();
+}
+
+fn apply_tag(style: &mut Style, name: &str, value: Option<&str>) -> Result<(), String> {
+    match (name, value) {
+        ("b", None) => style.bold = true,
+        ("i", None) => style.italic = true,
+        ("u", None) => style.underline = true,
+        ("color", Some(value)) => style.color = Some(parse_color(value)?),
+        ("size", Some(value)) => match value.parse::<f32>() {
+            Ok(size) if size > 0.0 && size.is_finite() => style.size = Some(size),
+            _ => return Err(format!("invalid size '{}'", value)),
+        },
+        ("font", Some(value)) if !value.is_empty() => style.font = Some(value.to_string()),
+        ("font", Some(_)) => return Err("empty font name".to_string()),
+        ("b" | "i" | "u", Some(_)) => return Err(format!("<{}> takes no value", name)),
+        ("color" | "size" | "font", None) => return Err(format!("<{}> requires a value", name)),
+        _ => return Err(format!("unknown tag <{}>", name)),
+    }
+    Ok(())
+}
+
+fn parse_color(value: &str) -> Result<[f32; 4], String> {
+    let invalid = || format!("invalid color '{}', expected #rrggbb or #rrggbbaa", value);
+
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if (hex.len() != 6 && hex.len() != 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let mut color = [1.0; 4];
+    for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap() as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Span, Style};
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let spans = parse("Hi <b>bold <i>both</i></b> <color=#ff0000>red</color>").unwrap();
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let both = Style {
+            italic: true,
+            ..bold.clone()
+        };
+        let red = Style {
+            color: Some([1.0, 0.0, 0.0, 1.0]),
+            ..Style::default()
+        };
+        assert_eq!(
+            spans,
+            vec![
+                span("Hi ", Style::default()),
+                span("bold ", bold),
+                span("both", both),
+                span(" ", Style::default()),
+                span("red", red),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let spans = parse("<size=24><font=Liberation Sans>a&lt;b</font></size>").unwrap();
+        let style = Style {
+            size: Some(24.0),
+            font: Some("Liberation Sans".to_string()),
+            ..Style::default()
+        };
+        assert_eq!(spans, vec![span("a<b", style)]);
+
+        // Empty tags don't split spans
+        assert_eq!(
+            parse("a<u></u>b").unwrap(),
+            vec![span("ab", Style::default())]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |markup: &str| {
+            let e = parse(markup).unwrap_err();
+            (e.start, e.end)
+        };
+        assert_eq!(err("ab<b>c</i>"), (6, 10));
+        assert_eq!(err("ab</b>"), (2, 6));
+        assert_eq!(err("a<b>c"), (1, 4));
+        assert_eq!(err("ab<color=#ff0000>x"), (2, 17));
+        assert_eq!(err("a<b"), (1, 3));
+        assert_eq!(err("a>"), (1, 2));
+        assert_eq!(err("<blink>x</blink>"), (0, 7));
+        assert_eq!(err("<color=red>x</color>"), (0, 11));
+        assert_eq!(err("<size=-1>x</size>"), (0, 9));
+        assert_eq!(err("<b=1>x</b>"), (0, 5));
+
+        let e = parse("<b>x</i>").unwrap_err();
+        assert_eq!(e.to_string(), "expected </b>, found </i> at bytes 4..8");
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    bezier::{Bezier2, Vec2},
+    grid::{transform_glyph_verts, GlVertex},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+// A path for text to follow, stored as a polyline with the cumulative
+// length at each point. Curves are flattened into line segments.
+pub struct Path {
+    points: Vec<Vec2>,
+    lengths: Vec<f32>,
+}
+
+impl Path {
+    pub fn from_polyline(points: &[Vec2]) -> Path {
+        assert!(points.len() >= 2, "A path needs at least two points");
+
+        let mut lengths = Vec::with_capacity(points.len());
+        let mut length = 0.0;
+        lengths.push(length);
+        for pair in points.windows(2) {
+            length += distance(pair[0], pair[1]);
+            lengths.push(length);
+        }
+
+        Path {
+            points: points.to_vec(),
+            lengths,
+        }
+    }
+
+    pub fn from_bezier(bezier: &Bezier2, segments: usize) -> Path {
+        let segments = segments.max(1);
+        let points: Vec<Vec2> = (0..=segments)
+            .map(|i| bezier.point_at(i as f32 / segments as f32))
+            .collect();
+        Path::from_polyline(&points)
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap()
+    }
+
+    // Point and tangent angle (radians) at a distance along the path.
+    // Distances outside the path continue along the first or last
+    // segment.
+    pub fn sample(&self, dist: f32) -> (Vec2, f32) {
+        // Index of the segment containing dist, skipping zero length ones
+        let mut i = self.lengths.partition_point(|l| *l < dist).max(1) - 1;
+        i = i.min(self.points.len() - 2);
+        while i + 2 < self.points.len() && self.lengths[i + 1] <= self.lengths[i] {
+            i += 1;
+        }
+
+        let (a, b) = (self.points[i], self.points[i + 1]);
+        let seg_length = self.lengths[i + 1] - self.lengths[i];
+        let t = if seg_length > 0.0 {
+            (dist - self.lengths[i]) / seg_length
+        } else {
+            0.0
+        };
+
+        let point = Vec2::from(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+        (point, (b.y - a.y).atan2(b.x - a.x))
+    }
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    (dx * dx + dy * dy).sqrt()
+}
+
+// Bends a line of glyphs onto a path. `verts` holds six vertices per
+// glyph laid out on a straight baseline at y = 0 starting from x = 0,
+// as insert_curves does, and `advances` the advance of each glyph.
+// Every glyph is rotated to the path tangent at the midpoint of its
+// advance, with its baseline on the path. `start_offset` shifts the
+// text along the path after alignment.
+pub fn place_on_path(
+    verts: &mut [GlVertex],
+    advances: &[f32],
+    path: &Path,
+    start_offset: f32,
+    align: Align,
+) {
+    assert_eq!(verts.len(), advances.len() * 6);
+
+    let total: f32 = advances.iter().sum();
+    let mut pen = start_offset
+        + match align {
+            Align::Start => 0.0,
+            Align::Center => (path.length() - total) / 2.0,
+            Align::End => path.length() - total,
+        };
+
+    let mut origin = 0.0;
+    for (quad, advance) in verts.chunks_mut(6).zip(advances) {
+        let mid = advance / 2.0;
+        let (point, angle) = path.sample(pen + mid);
+        transform_glyph_verts(
+            quad,
+            Vec2::from(origin + mid, 0.0),
+            angle,
+            1.0,
+            Vec2::from(point.x - (origin + mid), point.y),
+        );
+        pen += advance;
+        origin += advance;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bezier::{Bezier2, Vec2},
+        grid::GlVertex,
+    };
+
+    use super::{place_on_path, Align, Path};
+
+    fn assert_close(a: Vec2, b: (f32, f32)) {
+        let (x, y) = (a.x, a.y);
+        assert!(
+            (x - b.0).abs() < 1e-3 && (y - b.1).abs() < 1e-3,
+            "({}, {}) != {:?}",
+            x,
+            y,
+            b
+        );
+    }
+
+    fn quad(x0: f32, x1: f32, height: f32) -> Vec<GlVertex> {
+        let pos = [
+            (x0, 0.0),
+            (x1, 0.0),
+            (x0, height),
+            (x1, height),
+            (x0, height),
+            (x1, 0.0),
+        ];
+        pos.iter()
+            .map(|(x, y)| GlVertex {
+                pos: Vec2::from(*x, *y),
+                ..GlVertex::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_path_sample() {
+        let path = Path::from_polyline(&[
+            Vec2::from(0.0, 0.0),
+            Vec2::from(10.0, 0.0),
+            Vec2::from(10.0, 0.0),
+            Vec2::from(10.0, 10.0),
+        ]);
+        assert_eq!(path.length(), 20.0);
+
+        let (p, angle) = path.sample(5.0);
+        assert_close(p, (5.0, 0.0));
+        assert_eq!(angle, 0.0);
+
+        let (p, angle) = path.sample(15.0);
+        assert_close(p, (10.0, 5.0));
+        assert!((angle - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+
+        // Extrapolated past either end
+        assert_close(path.sample(-2.0).0, (-2.0, 0.0));
+        assert_close(path.sample(22.0).0, (10.0, 12.0));
+    }
+
+    #[test]
+    fn test_path_from_bezier() {
+        let bezier = Bezier2::from(0.0, 0.0, 10.0, 0.0, 5.0, 0.0);
+        let path = Path::from_bezier(&bezier, 8);
+        assert!((path.length() - 10.0).abs() < 1e-4);
+        assert_close(path.sample(2.5).0, (2.5, 0.0));
+    }
+
+    #[test]
+    fn test_place_on_path() {
+        // Two glyphs with advance 4, on a path going straight up
+        let mut verts = quad(1.0, 3.0, 2.0);
+        verts.extend(quad(5.0, 7.0, 2.0));
+        let path = Path::from_polyline(&[Vec2::from(0.0, 0.0), Vec2::from(0.0, 20.0)]);
+
+        place_on_path(&mut verts, &[4.0, 4.0], &path, 0.0, Align::Start);
+        // Baseline now runs up the path with the glyph tops to the left
+        assert_close(verts[0].pos, (0.0, 1.0));
+        assert_close(verts[3].pos, (-2.0, 3.0));
+        assert_close(verts[6].pos, (0.0, 5.0));
+        assert_close(verts[9].pos, (-2.0, 7.0));
+
+        let mut verts = quad(1.0, 3.0, 2.0);
+        place_on_path(&mut verts, &[4.0], &path, 1.0, Align::Center);
+        assert_close(verts[0].pos, (0.0, 10.0));
+        let mut verts = quad(1.0, 3.0, 2.0);
+        place_on_path(&mut verts, &[4.0], &path, 0.0, Align::End);
+        assert_close(verts[1].pos, (0.0, 19.0));
+    }
+}
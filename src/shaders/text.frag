@@ -3,8 +3,11 @@ precision highp float;
 #define numSS 4
 #define pi 3.1415926535897932384626433832795
 #define kPixelWindowSize 1.0
+#define kGridAtlasLayers 2
 
 uniform sampler2D uGridAtlas;
+uniform usampler2D uGridAtlasWide; // Used instead when uWideIndices is set
+uniform bool uWideIndices;
 uniform samplerBuffer uGlyphData;
 
 in vec4 oColor;
@@ -117,6 +120,19 @@ void updateClosestCrossing(in vec2 porig[3], mat2 M, inout float closest, ivec2
 	}
 }
 
+ivec4 fetchIndices(ivec2 coord)
+{
+	if (uWideIndices) {
+		return ivec4(texelFetch(uGridAtlasWide, coord, 0));
+	}
+	return ivec4(texelFetch(uGridAtlas, coord, 0) * 255.0);
+}
+
+int gridAtlasHeight()
+{
+	return uWideIndices ? textureSize(uGridAtlasWide, 0).y : textureSize(uGridAtlas, 0).y;
+}
+
 mat2 inverse(mat2 m)
 {
 	return mat2(m[1][1],-m[0][1], -m[1][0], m[0][0])
@@ -134,7 +150,7 @@ void main()
 	float theta = pi/float(numSS);
 	mat2 rotM = mat2(cos(theta), sin(theta), -sin(theta), cos(theta)); // note this is column major ordering
 
-	ivec4 indices1 = ivec4(texelFetch(uGridAtlas, indicesCoord, 0) * 255.0);
+	ivec4 indices1 = fetchIndices(indicesCoord);
 
 	// The mid-inside flag is encoded by the order of the beziers indices.
 	// See write_vgrid_cell_to_buffer() for details.
@@ -151,15 +167,23 @@ void main()
 
 	mat2 midTransform = getUnitLineMatrix(oNormCoord, cellMid);
 
-	for (int bezierIndex=0; bezierIndex<4; bezierIndex++) {
-		int coordIndex;
+	// The grid atlas is made of kGridAtlasLayers layers stacked
+	// vertically. Cells with more than four beziers continue at the same
+	// position in the next layer.
+	int layerHeight = gridAtlasHeight() / kGridAtlasLayers;
+	ivec4 indices = indices1;
+
+	for (int bezierIndex=0; bezierIndex<4*kGridAtlasLayers; bezierIndex++) {
+		if (bezierIndex > 0 && bezierIndex % 4 == 0) {
+			// More indices are flagged by the order of the last two.
+			// See write_vgrid_cell_to_buffer() for details.
+			bool moreThanFourIndices = indices[2] > indices[3] && indices[3] >= 2;
+			if (!moreThanFourIndices) break;
+			ivec2 coord = indicesCoord + ivec2(0, layerHeight * (bezierIndex / 4));
+			indices = fetchIndices(coord);
+		}
 
-		//if (bezierIndex < 4) {
-			coordIndex = indices1[bezierIndex];
-		//} else {
-		//	 if (!moreThanFourIndices) break;
-		//	 coordIndex = indices2[bezierIndex-4];
-		//}
+		int coordIndex = indices[bezierIndex % 4];
 
 		// Indices 0 and 1 are both "no bezier" -- see
 		// write_vgrid_cell_to_buffer() for why.
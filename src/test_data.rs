@@ -122,4 +122,15 @@ pub mod test_data {
 
         curves
     }
+
+    // Closed contour of straight beziers through the points
+    #[cfg(test)]
+    pub fn polygon(points: &[(f32, f32)]) -> Vec<Bezier2> {
+        (0..points.len())
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                Bezier2::from(a.0, a.1, b.0, b.1, (a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0)
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,36 @@
+use flib::{grid::Grid, Bezier2};
+
+// Shared with the unit tests. Its `crate::Bezier2` resolves to the import
+// above, so the helpers build curves the way a caller outside the crate
+// would.
+#[path = "../src/test_data.rs"]
+mod test_data;
+
+use test_data::test_data::{polygon, test_curves};
+
+fn square(size: f32) -> Vec<Bezier2> {
+    polygon(&[(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)])
+}
+
+#[test]
+fn test_insert_glyph_public_api() {
+    let mut grid = Grid::default();
+    let color = [1.0, 1.0, 1.0, 1.0];
+    grid.insert_glyph(7, &square(100.0), color).unwrap();
+    let one_glyph = grid.stats();
+    grid.insert_glyph(7, &square(100.0), color).unwrap();
+    grid.insert_curves(&test_curves(), color).unwrap();
+
+    let stats = grid.stats();
+    assert_eq!(stats.cached_glyphs, 1);
+    assert_eq!(stats.referenced_glyphs, 1);
+    assert!(stats.glyph_data_used > one_glyph.glyph_data_used);
+
+    // The cached glyph outlives its quads, the anonymous one doesn't
+    grid.remove_glyphs(0..3);
+    let stats = grid.stats();
+    assert_eq!(stats.cached_glyphs, 1);
+    assert_eq!(stats.referenced_glyphs, 0);
+    assert_eq!(stats.glyph_data_used, one_glyph.glyph_data_used);
+    assert_eq!(stats.grid_cells_used, one_glyph.grid_cells_used);
+}
@@ -1,7 +1,10 @@
 use memoffset::offset_of;
 use std::mem::size_of;
 
-use flib::grid::{kGridAtlasSize, text_framgent_shader, text_vertex_shader, GlVertex, Grid};
+use flib::atlas::IndexWidth;
+use flib::grid::{
+    kGridAtlasLayers, kGridAtlasSize, text_framgent_shader, text_vertex_shader, GlVertex, Grid,
+};
 
 use glow::{Context, HasContext, NativeProgram, NativeTexture};
 
@@ -31,12 +34,99 @@ fn load_shaders(
     shaders
 }
 
+// Textures for one atlas page of the grid
+struct AtlasPage {
+    glyph_buffer: glow::NativeBuffer,
+    glyph_texture: NativeTexture,
+    atlas_texture: NativeTexture,
+}
+
+unsafe fn create_atlas_page(ctx: &Context, grid: &mut Grid, page: usize) -> AtlasPage {
+    let (glyph_buffer, glyph_texture) = {
+        let glyph_buffer = ctx.create_buffer().unwrap();
+        upload_glyph_data(ctx, grid, page, glyph_buffer);
+        grid.glyph_data_buf_id = glyph_buffer.0.into();
+        let glyph_texture = ctx.create_texture().unwrap();
+        ctx.bind_texture(glow::TEXTURE_BUFFER, Some(glyph_texture));
+        grid.glyph_data_buf_tex_id = glyph_texture.0.into();
+        // not possible in glow?
+        gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA8, grid.glyph_data_buf_id);
+
+        (glyph_buffer, glyph_texture)
+    };
+
+    let atlas_texture = {
+        let atlas_texture = ctx.create_texture().unwrap();
+        ctx.bind_texture(glow::TEXTURE_2D, Some(atlas_texture));
+
+        upload_atlas_texture(ctx, grid, page);
+
+        // Integer (wide index) textures are incomplete with linear
+        // filtering, and the indices are only read with texelFetch anyway
+        ctx.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            glow::NEAREST as i32,
+        );
+        ctx.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAG_FILTER,
+            glow::NEAREST as i32,
+        );
+        ctx.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_WRAP_S,
+            glow::CLAMP_TO_EDGE as i32,
+        );
+        ctx.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_WRAP_R,
+            glow::CLAMP_TO_EDGE as i32,
+        );
+        atlas_texture
+    };
+    grid.atlases[page].uploaded = true;
+
+    AtlasPage {
+        glyph_buffer,
+        glyph_texture,
+        atlas_texture,
+    }
+}
+
+unsafe fn upload_atlas_texture(ctx: &Context, grid: &Grid, page: usize) {
+    let (internal_format, format, ty) = match grid.atlases[page].index_width {
+        IndexWidth::Narrow => (glow::RGBA8, glow::RGBA, glow::UNSIGNED_BYTE),
+        IndexWidth::Wide => (glow::RGBA16UI, glow::RGBA_INTEGER, glow::UNSIGNED_SHORT),
+    };
+    ctx.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        internal_format as i32,
+        kGridAtlasSize.into(),
+        (kGridAtlasSize * kGridAtlasLayers as u16).into(),
+        0,
+        format,
+        ty,
+        grid.atlas(page),
+    );
+}
+
+unsafe fn upload_glyph_data(ctx: &Context, grid: &Grid, page: usize, buffer: glow::NativeBuffer) {
+    ctx.bind_buffer(glow::TEXTURE_BUFFER, Some(buffer));
+    ctx.buffer_data_u8_slice(
+        glow::TEXTURE_BUFFER,
+        grid.glyphs(page).unwrap(),
+        glow::DYNAMIC_DRAW,
+    );
+}
+
 pub struct GLState {
     program: NativeProgram,
     vert_buffer: glow::NativeBuffer,
-    glyph_buffer: glow::NativeBuffer,
-    glyph_texture: NativeTexture,
+    pages: Vec<AtlasPage>,
     u_transform: Option<glow::NativeUniformLocation>,
+    u_wide_indices: Option<glow::NativeUniformLocation>,
 }
 
 impl GLState {
@@ -82,6 +172,10 @@ impl GLState {
             let u_glyph_data = ctx.get_uniform_location(program, "uGlyphData");
             ctx.uniform_1_i32(u_glyph_data.as_ref(), 1);
 
+            let u_grid_atlas_wide = ctx.get_uniform_location(program, "uGridAtlasWide");
+            ctx.uniform_1_i32(u_grid_atlas_wide.as_ref(), 2);
+            let u_wide_indices = ctx.get_uniform_location(program, "uWideIndices");
+
             let iden: [f32; 16] = [
                 3.39084e-05,
                 0.0,
@@ -117,68 +211,51 @@ impl GLState {
                 vert_buffer
             };
 
-            let (glyph_buffer, glyph_texture) = {
-                let glyph_buffer = ctx.create_buffer().unwrap();
-                ctx.bind_buffer(glow::TEXTURE_BUFFER, Some(glyph_buffer));
-                grid.glyph_data_buf_id = glyph_buffer.0.into();
-                let glyph_texture = ctx.create_texture().unwrap();
-                ctx.bind_texture(glow::TEXTURE_BUFFER, Some(glyph_texture));
-                grid.glyph_data_buf_tex_id = glyph_texture.0.into();
-                // not possible in glow?
-                gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA8, grid.glyph_data_buf_id);
-
-                (glyph_buffer, glyph_texture)
-            };
-
-            {
-                let atlas_texture = ctx.create_texture().unwrap();
-                ctx.bind_texture(glow::TEXTURE_BUFFER, Some(atlas_texture));
-
-                ctx.tex_image_2d(
-                    glow::TEXTURE_2D,
-                    0,
-                    glow::RGBA8 as i32,
-                    kGridAtlasSize.into(),
-                    kGridAtlasSize.into(),
-                    0,
-                    glow::RGBA,
-                    glow::UNSIGNED_BYTE,
-                    grid.atlas(),
-                );
-
-                ctx.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_MIN_FILTER,
-                    glow::LINEAR as i32,
-                );
-                ctx.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_MAG_FILTER,
-                    glow::LINEAR as i32,
-                );
-                ctx.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_WRAP_S,
-                    glow::CLAMP_TO_EDGE as i32,
-                );
-                ctx.tex_parameter_i32(
-                    glow::TEXTURE_2D,
-                    glow::TEXTURE_WRAP_R,
-                    glow::CLAMP_TO_EDGE as i32,
-                );
-                atlas_texture
-            };
+            let pages = (0..grid.atlases.len())
+                .map(|page| create_atlas_page(ctx, grid, page))
+                .collect();
 
             GLState {
                 program,
                 vert_buffer,
-                glyph_buffer,
-                glyph_texture,
+                pages,
                 u_transform,
+                u_wide_indices,
             }
         }
     }
 
+    // Brings the GPU copies up to date with the grid: textures for pages
+    // opened or dropped since the last sync, a full upload of the atlas
+    // and glyph data of every page that changed (all of them after
+    // Grid::compact) and the verts
+    pub fn sync(&mut self, ctx: &Context, grid: &mut Grid) {
+        unsafe {
+            while self.pages.len() > grid.atlases.len() {
+                let page = self.pages.pop().unwrap();
+                ctx.delete_buffer(page.glyph_buffer);
+                ctx.delete_texture(page.glyph_texture);
+                ctx.delete_texture(page.atlas_texture);
+            }
+            for page in 0..grid.atlases.len() {
+                if page == self.pages.len() {
+                    let textures = create_atlas_page(ctx, grid, page);
+                    self.pages.push(textures);
+                } else if !grid.atlases[page].uploaded {
+                    ctx.bind_texture(glow::TEXTURE_2D, Some(self.pages[page].atlas_texture));
+                    upload_atlas_texture(ctx, grid, page);
+                    upload_glyph_data(ctx, grid, page, self.pages[page].glyph_buffer);
+                    grid.atlases[page].uploaded = true;
+                }
+            }
+
+            ctx.bind_buffer(glow::ARRAY_BUFFER, Some(self.vert_buffer));
+            ctx.buffer_data_u8_slice(glow::ARRAY_BUFFER, grid.verts(), glow::DYNAMIC_DRAW);
+        }
+    }
+
+    // Draws the grid as of the last sync(). Call sync() after changing the
+    // grid, compact() in particular, or pages may show stale atlases.
     pub fn render(&self, ctx: &Context, grid: &Grid, transform: [f32; 16]) {
         unsafe {
             // render
@@ -190,12 +267,6 @@ impl GLState {
 
             ctx.uniform_matrix_4_f32_slice(self.u_transform.as_ref(), false, &transform);
 
-            ctx.bind_buffer(glow::TEXTURE_BUFFER, Some(self.glyph_buffer));
-            ctx.buffer_data_u8_slice(gl::TEXTURE_BUFFER, grid.glyphs(), glow::STREAM_DRAW);
-
-            ctx.active_texture(glow::TEXTURE1);
-            ctx.bind_texture(glow::TEXTURE_BUFFER, Some(self.glyph_texture));
-
             ctx.enable(glow::BLEND);
             ctx.bind_buffer(glow::ARRAY_BUFFER, Some(self.vert_buffer));
             ctx.enable_vertex_attrib_array(0);
@@ -226,7 +297,33 @@ impl GLState {
                 offset_of!(GlVertex, color) as *const gl::types::GLvoid,
             );
 
-            ctx.draw_arrays(glow::TRIANGLES, 0, grid.verts.len() as i32);
+            // Glyphs only reference the atlases of their own page, so
+            // draw each page's glyphs with that page's textures bound
+            for (page, ranges) in grid.draw_batches() {
+                // Pages opened since the last sync() have no textures yet
+                let textures = match self.pages.get(page) {
+                    Some(textures) => textures,
+                    None => continue,
+                };
+
+                // Integer and normalized samplers can't share a unit
+                let wide = grid.atlases[page].index_width == IndexWidth::Wide;
+                ctx.uniform_1_i32(self.u_wide_indices.as_ref(), wide as i32);
+                ctx.active_texture(if wide { glow::TEXTURE2 } else { glow::TEXTURE0 });
+                ctx.bind_texture(glow::TEXTURE_2D, Some(textures.atlas_texture));
+
+                ctx.active_texture(glow::TEXTURE1);
+                ctx.bind_texture(glow::TEXTURE_BUFFER, Some(textures.glyph_texture));
+
+                let firsts: Vec<i32> = ranges.iter().map(|r| r.start as i32).collect();
+                let counts: Vec<i32> = ranges.iter().map(|r| (r.end - r.start) as i32).collect();
+                gl::MultiDrawArrays(
+                    gl::TRIANGLES,
+                    firsts.as_ptr(),
+                    counts.as_ptr(),
+                    ranges.len() as i32,
+                );
+            }
 
             ctx.disable_vertex_attrib_array(0);
             ctx.disable_vertex_attrib_array(1);